@@ -1,8 +1,11 @@
+use crate::config::LimitsConfig;
+use crate::users::UserMap;
 use crate::utils::{hidden, prelude::*};
 use std::{
     fmt,
     ops::{Deref, DerefMut},
     rc::Rc,
+    sync::Arc,
 };
 
 pub struct Message(String);
@@ -111,6 +114,7 @@ impl<Title, Footer> MessageBuilder<Title, Footer> {
 pub struct ContentBuilder<T> {
     event: Rc<T>,
     messages: Option<Vec<String>>,
+    users: Arc<UserMap>,
 }
 
 impl<T> ContentBuilder<T>
@@ -121,9 +125,16 @@ where
         Self {
             event: event,
             messages: Some(Vec::new()),
+            users: Arc::default(),
         }
     }
 
+    /// users who are mentioned by their traQ names.
+    pub fn users(mut self, users: &Arc<UserMap>) -> Self {
+        self.users = Arc::clone(users);
+        self
+    }
+
     fn and_then<F: FnOnce(&mut Vec<String>) -> ()>(&mut self, f: F) {
         match self.messages.as_mut() {
             Some(v) => f(v),
@@ -163,6 +174,7 @@ where
         Self {
             event: self.event,
             messages: self.messages,
+            users: self.users,
         }
     }
 
@@ -195,7 +207,7 @@ where
     }
 
     pub fn group(mut self, f: fn(_self: ContentBuilder<T>) -> String) -> ContentBuilder<T> {
-        let t = f(ContentBuilder::new(Rc::clone(&self.event)));
+        let t = f(ContentBuilder::new(Rc::clone(&self.event)).users(&self.users));
         self.and_then(|v| v.push(t));
         self
     }
@@ -206,10 +218,16 @@ where
     T: TAssignee,
 {
     pub fn assignees(mut self) -> ContentBuilder<T> {
-        let assignees: Vec<String> = self.event.assignees().into_iter().map(|a| a.md()).collect();
-        let msg = truncate_msg(assignees, 2);
-
-        self.and_then(|v| v.push(msg));
+        let assignees: Vec<String> = self
+            .event
+            .assignees()
+            .into_iter()
+            .map(|a| a.md(&self.users))
+            .collect();
+        if !assignees.is_empty() {
+            let msg = format!("Assignees: {}", truncate_msg(assignees, 2));
+            self.and_then(|v| v.push(msg));
+        }
         self
     }
 }
@@ -227,14 +245,35 @@ where
     }
 }
 
+impl<T> ContentBuilder<T>
+where
+    T: TDiscussion,
+{
+    pub fn discussion(mut self) -> ContentBuilder<T> {
+        match self.event.discussion() {
+            Some(d) => self.and_then(|v| v.push(d.link_md())),
+            None => self.messages = None,
+        }
+        self
+    }
+
+    pub fn category(mut self) -> ContentBuilder<T> {
+        let msg = self.event.discussion().map(|v| v.category_md());
+        self.push_some_msg(msg);
+        self
+    }
+}
+
 impl<T> ContentBuilder<T>
 where
     T: TLabel,
 {
     pub fn labels(mut self) -> ContentBuilder<T> {
-        let labels = self.event.labels().into_iter().map(|l| l.md()).collect();
-        let msg = truncate_msg(labels, 2);
-        self.push_msg(msg);
+        let labels: Vec<String> = self.event.labels().into_iter().map(|l| l.md()).collect();
+        if !labels.is_empty() {
+            let msg = format!("Labels: {}", truncate_msg(labels, 2));
+            self.push_msg(msg);
+        }
         self
     }
 }
@@ -247,6 +286,24 @@ where
         self.push_some_msg(msg);
         self
     }
+
+    /// branches, diff stats, draft state and milestone in one line,
+    /// and the requested reviewers in another.
+    pub fn pr_details(mut self) -> ContentBuilder<T> {
+        if let Some(pr) = self.event.pr() {
+            let mut details = vec![pr.branches_md(), pr.diff_md()];
+            if pr.is_draft() {
+                details.push("Draft".to_owned());
+            }
+            if let Some(milestone) = pr.milestone_md() {
+                details.push(format!("Milestone {}", milestone));
+            }
+            self.push_msg(details.join(" | "));
+            let reviewers = pr.reviewers_md(&self.users);
+            self.push_some_msg(reviewers.map(|r| format!("Reviewers: {}", r)));
+        }
+        self
+    }
 }
 
 impl<T> ContentBuilder<T>
@@ -265,7 +322,7 @@ where
     T: TAction,
 {
     pub fn action(mut self) -> ContentBuilder<T> {
-        let msg = self.event.action().map(|v| v.md());
+        let msg = self.event.action().map(|v| v.md(&self.users));
         self.push_msg_or_none(msg);
         self
     }
@@ -275,13 +332,47 @@ impl<T> ContentBuilder<T>
 where
     T: TCommit,
 {
-    pub fn commit(mut self) -> ContentBuilder<T> {
-        let commits: Vec<String> = self.event.commits().into_iter().map(|c| c.md()).collect();
-        if commits.len() < 1 {
+    /// lists the subjects of at most `limit` commits. merge commits are only counted,
+    /// unless the push has no other commits.
+    pub fn commit(mut self, limit: usize) -> ContentBuilder<T> {
+        let (mut merges, mut commits): (Vec<_>, Vec<_>) =
+            self.event.commits().into_iter().partition(|c| c.is_merge());
+        if commits.is_empty() {
+            std::mem::swap(&mut commits, &mut merges);
+        }
+        if commits.is_empty() {
             self.none();
-        } else {
-            self.append_msg(commits);
+            return self;
+        }
+
+        let more = commits.len().saturating_sub(limit);
+        let mut msgs: Vec<String> = commits
+            .iter()
+            .take(limit)
+            .map(|c| c.md(&self.users))
+            .collect();
+
+        let mut rest = Vec::new();
+        match (more, self.event.commits_truncated()) {
+            (0, false) => {}
+            (0, true) => rest.push("more".to_owned()),
+            (n, false) => rest.push(format!("{} more", n)),
+            (n, true) => rest.push(format!("{}+ more", n)),
+        }
+        match merges.len() {
+            0 => {}
+            1 => rest.push("1 merge commit".to_owned()),
+            n => rest.push(format!("{} merge commits", n)),
+        }
+        if !rest.is_empty() {
+            let rest = format!("…and {}", rest.join(", "));
+            let msg = match self.event.commits_url() {
+                Some(url) => format!("[{}]({})", rest, url),
+                None => rest,
+            };
+            msgs.push(msg);
         }
+        self.append_msg(msgs);
         self
     }
 }
@@ -290,13 +381,61 @@ impl<T> ContentBuilder<T>
 where
     T: TComment,
 {
-    pub fn comment(mut self) -> ContentBuilder<T> {
-        let comment = self.event.comment().map(|v| v.comment());
+    pub fn comment(mut self, limits: &LimitsConfig) -> ContentBuilder<T> {
+        let comment = self.event.comment().map(|v| v.body(&self.users, limits));
         self.push_some_msg(comment);
         self
     }
 }
 
+impl<T> ContentBuilder<T>
+where
+    T: TGitRef,
+{
+    pub fn git_ref(mut self) -> ContentBuilder<T> {
+        let msg = self.event.git_ref().map(|v| v.md());
+        self.push_msg_or_none(msg);
+        self
+    }
+}
+
+impl<T> ContentBuilder<T>
+where
+    T: TRelease,
+{
+    pub fn release(mut self) -> ContentBuilder<T> {
+        let msg = self.event.release().map(|v| v.link_md());
+        self.push_msg_or_none(msg);
+        self
+    }
+
+    pub fn release_body(mut self, limits: &LimitsConfig) -> ContentBuilder<T> {
+        let body = self
+            .event
+            .release()
+            .and_then(|v| v.truncated_body(&self.users, limits));
+        self.push_some_msg(body);
+        self
+    }
+}
+
+impl<T> ContentBuilder<T>
+where
+    T: TWorkflow,
+{
+    pub fn workflow(mut self) -> ContentBuilder<T> {
+        let msg = self.event.workflow().map(|v| v.link_md());
+        self.push_msg_or_none(msg);
+        self
+    }
+
+    pub fn head_commit(mut self) -> ContentBuilder<T> {
+        let msg = self.event.workflow().map(|v| v.head_commit_md(&self.users));
+        self.push_some_msg(msg);
+        self
+    }
+}
+
 impl<T> ContentBuilder<T>
 where
     T: TReview,
@@ -316,8 +455,8 @@ where
 
 fn truncate_msg(v: Vec<String>, limit: usize) -> String {
     if v.len() <= limit {
-        v.into_iter().fold("".to_string(), |acc, x| acc + "," + &x)
+        v.join(", ")
     } else {
-        v[0].clone() + &v[1] + &format!("...{} mores", v.len() - limit)
+        format!("{} ...{} mores", v[..limit].join(", "), v.len() - limit)
     }
 }
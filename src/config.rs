@@ -0,0 +1,555 @@
+use crate::error::MyError;
+use crate::filter::FilterRule;
+use chrono::{DateTime, Utc};
+use glob::Pattern;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub routes: Vec<Route>,
+    #[serde(default)]
+    pub unsupported: UnsupportedPolicy,
+    /// star/fork counts which are celebrated in `milestone` mode.
+    #[serde(default = "default_milestones")]
+    pub milestones: Vec<u64>,
+    #[serde(default)]
+    pub outbox: OutboxConfig,
+    #[serde(default)]
+    pub store: StoreConfig,
+    #[serde(default)]
+    pub dedupe: DedupeConfig,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub github: GithubConfig,
+    /// message templates keyed by `<event>` or `<event>.<action>`.
+    #[serde(default)]
+    pub templates: HashMap<String, String>,
+    /// rules applied to every route.
+    #[serde(default)]
+    pub filters: Vec<FilterRule>,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    /// toml file which maps github users to traQ users, see `users.example.toml`.
+    pub users: Option<PathBuf>,
+}
+
+/// size limits of messages. a cut section ends with a `…(read more)` link to github.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LimitsConfig {
+    /// characters of an issue, pull request, comment or release body.
+    #[serde(default = "default_body_chars")]
+    pub body_chars: usize,
+    #[serde(default = "default_body_lines")]
+    pub body_lines: usize,
+    /// commits listed for a push.
+    #[serde(default = "default_commits")]
+    pub commits: usize,
+    /// a longer message is split into several posts.
+    #[serde(default = "default_message_chars")]
+    pub message_chars: usize,
+}
+
+fn default_body_chars() -> usize {
+    500
+}
+
+fn default_body_lines() -> usize {
+    20
+}
+
+fn default_commits() -> usize {
+    10
+}
+
+fn default_message_chars() -> usize {
+    10000
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            body_chars: default_body_chars(),
+            body_lines: default_body_lines(),
+            commits: default_commits(),
+            message_chars: default_message_chars(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct GithubConfig {
+    /// accepted webhook secrets. list the new one next to the old one while rotating.
+    #[serde(default)]
+    pub secrets: Vec<GithubSecret>,
+    /// accept the legacy `X-Hub-Signature` (HMAC-SHA1) header when SHA-256 is not sent.
+    #[serde(default)]
+    pub allow_sha1: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GithubSecret {
+    pub secret: String,
+    /// RFC 3339 date time after which the secret is rejected.
+    pub valid_until: Option<DateTime<Utc>>,
+}
+
+impl GithubSecret {
+    pub fn new(secret: impl Into<String>) -> Self {
+        Self {
+            secret: secret.into(),
+            valid_until: None,
+        }
+    }
+
+    pub fn is_valid_at(&self, now: DateTime<Utc>) -> bool {
+        self.valid_until.map(|until| now <= until).unwrap_or(true)
+    }
+}
+
+/// settings of the client used for outgoing requests.
+#[derive(Debug, Clone, Deserialize)]
+pub struct HttpConfig {
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// timeout of a whole request, including reading the response.
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// e.g. `http://proxy.example.com:8080`.
+    pub proxy: Option<String>,
+    /// PEM files trusted in addition to the system roots.
+    #[serde(default)]
+    pub root_certificates: Vec<PathBuf>,
+    #[serde(default = "default_user_agent")]
+    pub user_agent: String,
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    10
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+fn default_user_agent() -> String {
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")).to_owned()
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: default_connect_timeout_secs(),
+            timeout_secs: default_timeout_secs(),
+            proxy: None,
+            root_certificates: Vec::new(),
+            user_agent: default_user_agent(),
+        }
+    }
+}
+
+/// skipping of webhooks redelivered with the same `X-GitHub-Delivery`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DedupeConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_dedupe_ttl_secs")]
+    pub ttl_secs: u64,
+    #[serde(default = "default_dedupe_capacity")]
+    pub capacity: usize,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_dedupe_ttl_secs() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_dedupe_capacity() -> usize {
+    10000
+}
+
+impl Default for DedupeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl_secs: default_dedupe_ttl_secs(),
+            capacity: default_dedupe_capacity(),
+        }
+    }
+}
+
+fn default_milestones() -> Vec<u64> {
+    vec![10, 50, 100, 500, 1000, 5000, 10000]
+}
+
+/// what to do with events which have no handler.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnsupportedPolicy {
+    /// acknowledge and drop.
+    Ignore,
+    /// acknowledge and post a generic message built from the raw payload.
+    Fallback,
+}
+
+impl Default for UnsupportedPolicy {
+    fn default() -> Self {
+        UnsupportedPolicy::Ignore
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OutboxConfig {
+    /// log file of undelivered messages.
+    #[serde(default = "default_outbox_path")]
+    pub path: PathBuf,
+    /// a message is moved to the dead letters after this many failed deliveries.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_base_delay_secs")]
+    pub base_delay_secs: u64,
+    #[serde(default = "default_max_delay_secs")]
+    pub max_delay_secs: u64,
+}
+
+fn default_outbox_path() -> PathBuf {
+    PathBuf::from("outbox.jsonl")
+}
+
+fn default_max_attempts() -> u32 {
+    8
+}
+
+fn default_base_delay_secs() -> u64 {
+    2
+}
+
+fn default_max_delay_secs() -> u64 {
+    600
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        Self {
+            path: default_outbox_path(),
+            max_attempts: default_max_attempts(),
+            base_delay_secs: default_base_delay_secs(),
+            max_delay_secs: default_max_delay_secs(),
+        }
+    }
+}
+
+/// ids of the messages posted with the traQ bot API.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StoreConfig {
+    #[serde(default = "default_store_path")]
+    pub path: PathBuf,
+}
+
+fn default_store_path() -> PathBuf {
+    PathBuf::from("messages.json")
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        Self {
+            path: default_store_path(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Route {
+    pub repository: GlobPattern,
+    pub targets: Vec<Target>,
+    #[serde(default)]
+    pub workflow: WorkflowConfig,
+    /// how `star` and `fork` events are posted.
+    #[serde(default)]
+    pub activity: ActivityMode,
+    /// overrides the global templates for this route.
+    #[serde(default)]
+    pub templates: HashMap<String, String>,
+    /// checked after the global filters.
+    #[serde(default)]
+    pub filters: Vec<FilterRule>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ActivityMode {
+    /// post only when the star/fork count reaches one of `milestones`.
+    Milestone,
+    /// post every event.
+    Every,
+    /// never post.
+    Off,
+}
+
+impl Default for ActivityMode {
+    fn default() -> Self {
+        ActivityMode::Milestone
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct WorkflowConfig {
+    /// branches whose workflow runs are reported. empty means every branch.
+    #[serde(default)]
+    pub branches: Vec<String>,
+    /// also report failed jobs (`workflow_job`), not only failed runs.
+    #[serde(default)]
+    pub jobs: bool,
+}
+
+impl WorkflowConfig {
+    pub fn watches(&self, branch: &str) -> bool {
+        self.branches.is_empty() || self.branches.iter().any(|b| b == branch)
+    }
+}
+
+/// destination of messages. `kind` defaults to `traq`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Target {
+    Traq(TraqTarget),
+    #[serde(rename = "traq_bot")]
+    TraqBot(TraqBotTarget),
+    Slack(SlackTarget),
+    Discord(DiscordTarget),
+    Json(JsonTarget),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum TaggedTarget {
+    Traq(TraqTarget),
+    #[serde(rename = "traq_bot")]
+    TraqBot(TraqBotTarget),
+    Slack(SlackTarget),
+    Discord(DiscordTarget),
+    Json(JsonTarget),
+}
+
+impl<'de> Deserialize<'de> for Target {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut value = Value::deserialize(deserializer)?;
+        if let Some(map) = value.as_object_mut() {
+            map.entry("kind").or_insert_with(|| "traq".into());
+        }
+        let target = match TaggedTarget::deserialize(value).map_err(de::Error::custom)? {
+            TaggedTarget::Traq(t) => Target::Traq(t),
+            TaggedTarget::TraqBot(t) => Target::TraqBot(t),
+            TaggedTarget::Slack(t) => Target::Slack(t),
+            TaggedTarget::Discord(t) => Target::Discord(t),
+            TaggedTarget::Json(t) => Target::Json(t),
+        };
+        Ok(target)
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Traq(t) => write!(f, "traq:{}", t.id),
+            Target::TraqBot(t) => write!(f, "traq_bot:{}", t.channel_id),
+            Target::Slack(t) => write!(f, "slack:{}", without_token(&t.url)),
+            Target::Discord(t) => write!(f, "discord:{}", without_token(&t.url)),
+            Target::Json(t) => write!(f, "json:{}", t.url),
+        }
+    }
+}
+
+/// the last segment of slack and discord webhook urls is their secret.
+fn without_token(url: &str) -> &str {
+    url.rsplitn(2, '/').last().unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraqTarget {
+    pub id: String,
+    pub secret: String,
+    /// traQ API base url, e.g. `https://q.trap.jp/api/v3`.
+    #[serde(default = "default_traq_base_url")]
+    pub base_url: String,
+    /// posts with `embed=1`, so that `@name` notifies the user.
+    #[serde(default = "default_true")]
+    pub embed: bool,
+}
+
+pub const DEFAULT_TRAQ_BASE_URL: &str = "https://q.trap.jp/api/v3";
+
+fn default_traq_base_url() -> String {
+    DEFAULT_TRAQ_BASE_URL.to_owned()
+}
+
+impl TraqTarget {
+    pub fn new(id: impl Into<String>, secret: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            secret: secret.into(),
+            base_url: default_traq_base_url(),
+            embed: true,
+        }
+    }
+
+    pub fn webhook_url(&self) -> String {
+        let url = format!(
+            "{}/webhooks/{}",
+            self.base_url.trim_end_matches('/'),
+            self.id
+        );
+        if self.embed {
+            url + "?embed=1"
+        } else {
+            url
+        }
+    }
+}
+
+/// channel posted to with the traQ bot API.
+///
+/// the message of an issue or a pull request is edited by later events about it
+/// instead of posting a new one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraqBotTarget {
+    pub channel_id: String,
+    /// access token of the bot.
+    pub token: String,
+    #[serde(default = "default_traq_base_url")]
+    pub base_url: String,
+    /// posts with `embed=true`, so that `@name` notifies the user.
+    #[serde(default = "default_true")]
+    pub embed: bool,
+}
+
+impl TraqBotTarget {
+    pub fn messages_url(&self) -> String {
+        format!(
+            "{}/channels/{}/messages",
+            self.base_url.trim_end_matches('/'),
+            self.channel_id
+        )
+    }
+
+    pub fn message_url(&self, message_id: &str) -> String {
+        format!(
+            "{}/messages/{}",
+            self.base_url.trim_end_matches('/'),
+            message_id
+        )
+    }
+}
+
+/// slack incoming webhook.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlackTarget {
+    /// e.g. `https://hooks.slack.com/services/T000/B000/XXXX`.
+    pub url: String,
+}
+
+/// discord webhook. issues, pull requests and discussions are posted as embeds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscordTarget {
+    /// e.g. `https://discord.com/api/webhooks/000/XXXX`.
+    pub url: String,
+}
+
+/// endpoint which receives the normalized event as json.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonTarget {
+    pub url: String,
+    /// signs the body with HMAC-SHA256 in `X-Signature-256` when set.
+    pub secret: Option<String>,
+    /// wraps the document in a CloudEvents 1.0 envelope.
+    #[serde(default)]
+    pub cloudevents: bool,
+}
+
+/// glob pattern (`*`, `?`, `[...]`) such as `owner/*` or `release/*`.
+/// it also matches its own text, so that `dependabot[bot]` matches that login.
+#[derive(Debug, Clone)]
+pub struct GlobPattern(Pattern);
+
+impl GlobPattern {
+    pub fn new(pattern: &str) -> Result<Self, MyError> {
+        Pattern::new(pattern)
+            .map(GlobPattern)
+            .map_err(|e| MyError::ConfigError(e.to_string()))
+    }
+
+    pub fn matches(&self, s: &str) -> bool {
+        self.0.as_str() == s || self.0.matches(s)
+    }
+}
+
+impl<'de> Deserialize<'de> for GlobPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Pattern::new(&s).map(GlobPattern).map_err(de::Error::custom)
+    }
+}
+
+impl Config {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, MyError> {
+        let s = fs::read_to_string(path)?;
+        Self::from_toml(&s)
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self, MyError> {
+        let config = toml::from_str(s)?;
+        Ok(config)
+    }
+
+    /// config which sends every repository to one target.
+    pub fn single(target: Target) -> Self {
+        Self {
+            routes: vec![Route {
+                repository: GlobPattern::new("*").expect("valid pattern"),
+                targets: vec![target],
+                workflow: WorkflowConfig::default(),
+                activity: ActivityMode::default(),
+                templates: HashMap::new(),
+                filters: Vec::new(),
+            }],
+            unsupported: UnsupportedPolicy::default(),
+            milestones: default_milestones(),
+            outbox: OutboxConfig::default(),
+            store: StoreConfig::default(),
+            dedupe: DedupeConfig::default(),
+            http: HttpConfig::default(),
+            github: GithubConfig::default(),
+            templates: HashMap::new(),
+            filters: Vec::new(),
+            limits: LimitsConfig::default(),
+            users: None,
+        }
+    }
+
+    /// returns the first route which matches `repository` (`owner/repo`).
+    pub fn route(&self, repository: &str) -> Option<&Route> {
+        self.route_index(repository).map(|i| &self.routes[i])
+    }
+
+    pub fn route_index(&self, repository: &str) -> Option<usize> {
+        self.routes
+            .iter()
+            .position(|r| r.repository.matches(repository))
+    }
+
+    pub fn targets(&self, repository: &str) -> &[Target] {
+        self.route(repository)
+            .map(|r| r.targets.as_slice())
+            .unwrap_or(&[])
+    }
+}
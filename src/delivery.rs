@@ -0,0 +1,117 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// bounded set of `X-GitHub-Delivery` ids seen within `ttl`.
+pub struct Deliveries {
+    ttl: Duration,
+    capacity: usize,
+    seen: HashMap<String, Instant>,
+    order: VecDeque<(String, Instant)>,
+}
+
+impl Deliveries {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            seen: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// records `id` and returns `false` if it has already been seen.
+    pub fn insert(&mut self, id: &str, now: Instant) -> bool {
+        self.evict(now);
+        if self.seen.contains_key(id) {
+            return false;
+        }
+
+        self.seen.insert(id.to_owned(), now);
+        self.order.push_back((id.to_owned(), now));
+        while self.order.len() > self.capacity {
+            self.pop_front();
+        }
+        true
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        // the entry in `order` is skipped by `pop_front`
+        self.seen.remove(id);
+    }
+
+    fn evict(&mut self, now: Instant) {
+        while let Some((_, at)) = self.order.front() {
+            if now.duration_since(*at) < self.ttl {
+                break;
+            }
+            self.pop_front();
+        }
+    }
+
+    fn pop_front(&mut self) {
+        if let Some((id, at)) = self.order.pop_front() {
+            // the id may have been inserted again after it expired
+            if self.seen.get(&id) == Some(&at) {
+                self.seen.remove(&id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn rejects_seen_id() {
+        let mut deliveries = Deliveries::new(TTL, 10);
+        let now = Instant::now();
+        assert!(deliveries.insert("a", now));
+        assert!(!deliveries.insert("a", now + Duration::from_secs(1)));
+        assert!(deliveries.insert("b", now + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn accepts_id_again_after_ttl() {
+        let mut deliveries = Deliveries::new(TTL, 10);
+        let now = Instant::now();
+        assert!(deliveries.insert("a", now));
+        assert!(deliveries.insert("a", now + TTL));
+        assert!(!deliveries.insert("a", now + TTL + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn reinserted_id_is_not_evicted_by_its_old_entry() {
+        let mut deliveries = Deliveries::new(TTL, 2);
+        let now = Instant::now();
+        assert!(deliveries.insert("a", now));
+        let later = now + TTL;
+        // "a" expires and is inserted again
+        assert!(deliveries.insert("a", later));
+        assert!(deliveries.insert("b", later));
+        assert!(!deliveries.insert("a", later));
+    }
+
+    #[test]
+    fn evicts_oldest_over_capacity() {
+        let mut deliveries = Deliveries::new(TTL, 2);
+        let now = Instant::now();
+        assert!(deliveries.insert("a", now));
+        assert!(deliveries.insert("b", now));
+        assert!(deliveries.insert("c", now));
+        assert!(deliveries.insert("a", now));
+        assert!(!deliveries.insert("c", now));
+    }
+
+    #[test]
+    fn removed_id_is_accepted_again() {
+        let mut deliveries = Deliveries::new(TTL, 10);
+        let now = Instant::now();
+        assert!(deliveries.insert("a", now));
+        deliveries.remove("a");
+        assert!(deliveries.insert("a", now));
+        assert!(!deliveries.insert("a", now));
+    }
+}
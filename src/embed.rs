@@ -0,0 +1,209 @@
+use crate::users::UserMap;
+use crate::utils::{hidden, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+
+const COLOR_OPENED: u32 = 0x2cbe4e;
+const COLOR_CLOSED: u32 = 0xcb2431;
+const COLOR_MERGED: u32 = 0x6f42c1;
+
+/// structured form of a message for sinks which can show more than markdown.
+/// it is serialized in the shape of a discord embed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Embed {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<EmbedField>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub footer: Option<EmbedFooter>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbedField {
+    pub name: String,
+    pub value: String,
+    pub inline: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbedFooter {
+    pub text: String,
+}
+
+/// builds an `Embed` from the values extracted by the `utils` traits.
+///
+/// the colour is picked from the action for `opened`, `reopened`, `closed` and `merged`,
+/// and from the first label otherwise.
+/// users are not mapped to traQ users, since embeds are not posted to traQ.
+pub struct EmbedBuilder<T> {
+    event: Rc<T>,
+    embed: Embed,
+    action_color: Option<u32>,
+    label_color: Option<u32>,
+}
+
+impl<T> EmbedBuilder<T>
+where
+    T: hidden::Marker,
+{
+    pub fn new(event: Rc<T>) -> Self {
+        Self {
+            event,
+            embed: Embed::default(),
+            action_color: None,
+            label_color: None,
+        }
+    }
+
+    fn field(mut self, name: &str, value: String) -> Self {
+        if !value.is_empty() {
+            self.embed.fields.push(EmbedField {
+                name: name.to_owned(),
+                value,
+                inline: true,
+            });
+        }
+        self
+    }
+
+    /// `None` if there is nothing to link the embed to.
+    pub fn build(mut self) -> Option<Embed> {
+        self.embed.title.as_ref()?;
+        self.embed.color = self.action_color.or(self.label_color);
+        Some(self.embed)
+    }
+}
+
+impl<T> EmbedBuilder<T>
+where
+    T: TIssue,
+{
+    pub fn issue(mut self) -> Self {
+        if let Some(issue) = self.event.issue() {
+            self.embed.title = Some(issue.title());
+            self.embed.url = Some(issue.url().to_owned());
+        }
+        self
+    }
+}
+
+impl<T> EmbedBuilder<T>
+where
+    T: TPullRequest,
+{
+    pub fn pr(mut self) -> Self {
+        let pr = match self.event.pr() {
+            Some(pr) => pr,
+            None => return self,
+        };
+        self.embed.title = Some(if pr.is_draft() {
+            format!("[Draft] {}", pr.title())
+        } else {
+            pr.title()
+        });
+        self.embed.url = Some(pr.url().to_owned());
+        let reviewers = pr.reviewers_md(&UserMap::default());
+        self.field("Branch", pr.branches_md())
+            .field("Changes", pr.diff_md())
+            .field("Reviewers", reviewers.unwrap_or_default())
+            .field("Milestone", pr.milestone_md().unwrap_or_default())
+    }
+}
+
+impl<T> EmbedBuilder<T>
+where
+    T: TDiscussion,
+{
+    pub fn discussion(mut self) -> Self {
+        if let Some(discussion) = self.event.discussion() {
+            self.embed.title = Some(discussion.title());
+            self.embed.url = Some(discussion.url().to_owned());
+        }
+        self
+    }
+}
+
+impl<T> EmbedBuilder<T>
+where
+    T: TAction,
+{
+    pub fn action(mut self) -> Self {
+        if let Some(action) = self.event.action() {
+            self.action_color = match action.key() {
+                "opened" | "reopened" => Some(COLOR_OPENED),
+                "closed" => Some(COLOR_CLOSED),
+                "merged" => Some(COLOR_MERGED),
+                _ => None,
+            };
+            self.embed.description = Some(action.md(&UserMap::default()));
+        }
+        self
+    }
+}
+
+impl<T> EmbedBuilder<T>
+where
+    T: TComment,
+{
+    pub fn comment(mut self) -> Self {
+        if let Some(comment) = self.event.comment() {
+            let comment = comment.comment(&UserMap::default());
+            if !comment.is_empty() {
+                let description = match self.embed.description.take() {
+                    Some(d) => format!("{}\n\n{}", d, comment),
+                    None => comment,
+                };
+                self.embed.description = Some(description);
+            }
+        }
+        self
+    }
+}
+
+impl<T> EmbedBuilder<T>
+where
+    T: TAssignee,
+{
+    pub fn assignees(self) -> Self {
+        let assignees = self.event.assignees();
+        let value = assignees
+            .iter()
+            .map(|a| format!("`{}`", a.name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.field("Assignees", value)
+    }
+}
+
+impl<T> EmbedBuilder<T>
+where
+    T: TLabel,
+{
+    pub fn labels(mut self) -> Self {
+        let labels = self.event.labels();
+        self.label_color = labels.first().and_then(|l| l.rgb());
+        let value = labels.iter().map(|l| l.md()).collect::<Vec<_>>().join(", ");
+        self.field("Labels", value)
+    }
+}
+
+impl<T> EmbedBuilder<T>
+where
+    T: TRepository,
+{
+    pub fn repo(mut self) -> Self {
+        if let Some(repo) = self.event.repo() {
+            self.embed.footer = Some(EmbedFooter {
+                text: repo.full_name(),
+            });
+        }
+        self
+    }
+}
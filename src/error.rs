@@ -1,3 +1,4 @@
+use actix_web::http::StatusCode;
 use serde_json;
 use thiserror::Error;
 
@@ -12,8 +13,52 @@ pub enum MyError {
     #[error("unauthorized")]
     UnAuthorized,
 
+    #[error("missing header: {0}")]
+    MissingHeader(&'static str),
+
+    #[error("invalid header: {0}")]
+    InvalidHeader(&'static str),
+
+    #[error("missing signature: X-Hub-Signature-256 is required")]
+    MissingSignature,
+
+    #[error("malformed signature in {0}")]
+    MalformedSignature(&'static str),
+
+    #[error("unsupported content type: {0}, expected application/json")]
+    UnsupportedContentType(String),
+
+    #[error("delivery {0} has already been processed")]
+    DuplicateDelivery(String),
+
     #[error("invalid payload field")]
     ReadPayloadError,
+
+    #[error("io error")]
+    IoError(#[from] std::io::Error),
+
+    #[error("failed to parse config")]
+    ConfigParseError(#[from] toml::de::Error),
+
+    #[error("invalid config: {0}")]
+    ConfigError(String),
+
+    #[error("failed to render template")]
+    TemplateError(#[from] handlebars::RenderError),
 }
 
-impl actix_web::ResponseError for MyError {}
+impl actix_web::ResponseError for MyError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            MyError::SerdeJsonError(_)
+            | MyError::ReadPayloadError
+            | MyError::MissingHeader(_)
+            | MyError::InvalidHeader(_) => StatusCode::BAD_REQUEST,
+            MyError::UnAuthorized | MyError::MissingSignature | MyError::MalformedSignature(_) => {
+                StatusCode::UNAUTHORIZED
+            }
+            MyError::UnsupportedContentType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use crate::config::GlobPattern;
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterEffect {
+    /// events matching the rule are posted, other events of the same scope are dropped.
+    Allow,
+    /// events matching the rule are dropped.
+    Deny,
+}
+
+/// rule checked before an event is rendered.
+///
+/// every given condition has to match. conditions which are not set match anything.
+/// `events` and `repositories` also decide which events an `allow` rule applies to.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FilterRule {
+    pub effect: FilterEffect,
+    /// e.g. `push`, `issues`, `pull_request`.
+    #[serde(default)]
+    pub events: Vec<String>,
+    #[serde(default)]
+    pub repositories: Vec<GlobPattern>,
+    /// branch or tag name, e.g. `main`, `release/*`.
+    #[serde(default)]
+    pub branches: Vec<GlobPattern>,
+    /// `action` of the payload, e.g. `opened`.
+    #[serde(default)]
+    pub actions: Vec<String>,
+    /// login of the sender, e.g. `dependabot[bot]`.
+    #[serde(default)]
+    pub senders: Vec<GlobPattern>,
+    /// matches if the issue, pull request or discussion has one of these labels.
+    #[serde(default)]
+    pub labels: Vec<String>,
+    pub draft: Option<bool>,
+    pub bot: Option<bool>,
+}
+
+impl FilterRule {
+    fn applies_to(&self, facts: &Facts) -> bool {
+        (self.events.is_empty() || self.events.iter().any(|e| e == facts.event))
+            && matches_any(&self.repositories, facts.repository)
+    }
+
+    pub fn matches(&self, facts: &Facts) -> bool {
+        self.applies_to(facts)
+            && matches_any(&self.branches, facts.branch)
+            && matches_any(&self.senders, facts.sender)
+            && (self.actions.is_empty()
+                || facts
+                    .action
+                    .map(|a| self.actions.iter().any(|b| a == b))
+                    .unwrap_or(false))
+            && (self.labels.is_empty()
+                || self
+                    .labels
+                    .iter()
+                    .any(|l| facts.labels.contains(&l.as_str())))
+            && self.draft.map(|d| facts.draft == Some(d)).unwrap_or(true)
+            && self.bot.map(|b| facts.bot == b).unwrap_or(true)
+    }
+}
+
+fn matches_any(patterns: &[GlobPattern], value: Option<&str>) -> bool {
+    if patterns.is_empty() {
+        return true;
+    }
+    value
+        .map(|v| patterns.iter().any(|p| p.matches(v)))
+        .unwrap_or(false)
+}
+
+/// values of a webhook payload which the rules are checked against.
+#[derive(Debug)]
+pub struct Facts<'a> {
+    pub event: &'a str,
+    pub repository: Option<&'a str>,
+    pub branch: Option<&'a str>,
+    pub action: Option<&'a str>,
+    pub sender: Option<&'a str>,
+    pub labels: Vec<&'a str>,
+    pub draft: Option<bool>,
+    pub bot: bool,
+}
+
+impl<'a> Facts<'a> {
+    pub fn new(event: &'a str, payload: &'a Value) -> Self {
+        // the issue, pull request or discussion the event is about
+        let subject = ["pull_request", "issue", "discussion"]
+            .iter()
+            .map(|key| &payload[key])
+            .find(|v| v.is_object());
+
+        let branch = payload["ref"]
+            .as_str()
+            .map(|r| {
+                r.trim_start_matches("refs/heads/")
+                    .trim_start_matches("refs/tags/")
+            })
+            .or_else(|| payload["pull_request"]["base"]["ref"].as_str())
+            .or_else(|| payload["workflow_run"]["head_branch"].as_str())
+            .or_else(|| payload["workflow_job"]["head_branch"].as_str())
+            .or_else(|| payload["release"]["tag_name"].as_str());
+
+        let labels = subject
+            .and_then(|s| s["labels"].as_array())
+            .map(|labels| labels.iter().filter_map(|l| l["name"].as_str()).collect())
+            .unwrap_or_default();
+
+        let draft = subject
+            .and_then(|s| s["draft"].as_bool())
+            .or_else(|| payload["release"]["draft"].as_bool());
+
+        let sender = payload["sender"]["login"].as_str();
+        let bot = payload["sender"]["type"].as_str() == Some("Bot")
+            || sender.map(|s| s.ends_with("[bot]")).unwrap_or(false);
+
+        Self {
+            event,
+            repository: payload["repository"]["full_name"].as_str(),
+            branch,
+            action: payload["action"].as_str(),
+            sender,
+            labels,
+            draft,
+            bot,
+        }
+    }
+}
+
+/// an event is dropped if a `deny` rule matches it, or if `allow` rules apply to it
+/// and none of them matches.
+pub fn is_allowed<'r>(rules: impl IntoIterator<Item = &'r FilterRule>, facts: &Facts) -> bool {
+    let mut allowed = None;
+    for rule in rules {
+        match rule.effect {
+            FilterEffect::Deny if rule.matches(facts) => return false,
+            FilterEffect::Allow if rule.applies_to(facts) => {
+                allowed = Some(allowed.unwrap_or(false) || rule.matches(facts));
+            }
+            _ => {}
+        }
+    }
+    allowed.unwrap_or(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Deserialize)]
+    struct Rules {
+        filters: Vec<FilterRule>,
+    }
+
+    fn rules(toml: &str) -> Vec<FilterRule> {
+        toml::from_str::<Rules>(toml).unwrap().filters
+    }
+
+    fn allowed(rules: &[FilterRule], event: &str, payload: &Value) -> bool {
+        is_allowed(rules, &Facts::new(event, payload))
+    }
+
+    fn push(branch: &str) -> Value {
+        json!({
+            "ref": format!("refs/heads/{}", branch),
+            "repository": { "full_name": "o/repo" },
+            "sender": { "login": "alice", "type": "User" },
+        })
+    }
+
+    fn issue(labels: &[&str]) -> Value {
+        let labels: Vec<Value> = labels.iter().map(|l| json!({ "name": l })).collect();
+        json!({
+            "action": "opened",
+            "issue": { "labels": labels },
+            "repository": { "full_name": "o/repo" },
+            "sender": { "login": "alice", "type": "User" },
+        })
+    }
+
+    #[test]
+    fn allows_only_pushes_to_main() {
+        let rules = rules(
+            r#"
+            [[filters]]
+            effect = "allow"
+            events = ["push"]
+            branches = ["main"]
+            "#,
+        );
+        assert!(allowed(&rules, "push", &push("main")));
+        assert!(!allowed(&rules, "push", &push("feature")));
+        // other events are not in the scope of the rule
+        assert!(allowed(&rules, "issues", &issue(&[])));
+    }
+
+    #[test]
+    fn ignores_dependabot() {
+        let rules = rules(
+            r#"
+            [[filters]]
+            effect = "deny"
+            senders = ["dependabot[bot]"]
+            "#,
+        );
+        let mut payload = push("main");
+        assert!(allowed(&rules, "push", &payload));
+        payload["sender"] = json!({ "login": "dependabot[bot]", "type": "Bot" });
+        assert!(!allowed(&rules, "push", &payload));
+    }
+
+    #[test]
+    fn allows_only_issues_labelled_bug() {
+        let rules = rules(
+            r#"
+            [[filters]]
+            effect = "allow"
+            events = ["issues"]
+            labels = ["bug"]
+            "#,
+        );
+        assert!(allowed(&rules, "issues", &issue(&["bug", "ui"])));
+        assert!(!allowed(&rules, "issues", &issue(&["enhancement"])));
+        assert!(!allowed(&rules, "issues", &issue(&[])));
+    }
+
+    #[test]
+    fn scopes_allow_rules_by_repository() {
+        let rules = rules(
+            r#"
+            [[filters]]
+            effect = "allow"
+            repositories = ["o/*"]
+            branches = ["main"]
+            "#,
+        );
+        assert!(!allowed(&rules, "push", &push("feature")));
+
+        let mut other = push("feature");
+        other["repository"]["full_name"] = json!("x/repo");
+        assert!(allowed(&rules, "push", &other));
+    }
+
+    #[test]
+    fn deny_wins_over_allow() {
+        let rules = rules(
+            r#"
+            [[filters]]
+            effect = "allow"
+            events = ["push"]
+            branches = ["main"]
+
+            [[filters]]
+            effect = "deny"
+            bot = true
+            "#,
+        );
+        let mut payload = push("main");
+        payload["sender"] = json!({ "login": "renovate[bot]", "type": "Bot" });
+        assert!(!allowed(&rules, "push", &payload));
+    }
+
+    #[test]
+    fn derives_branch_of_each_event() {
+        let tag = json!({ "ref": "refs/tags/v1.0.0" });
+        assert_eq!(Facts::new("push", &push("main")).branch, Some("main"));
+        assert_eq!(Facts::new("push", &tag).branch, Some("v1.0.0"));
+
+        let pull_request = json!({
+            "pull_request": { "base": { "ref": "main" }, "head": { "ref": "feature" } },
+        });
+        assert_eq!(
+            Facts::new("pull_request", &pull_request).branch,
+            Some("main")
+        );
+
+        let release = json!({ "release": { "tag_name": "v1.0.0", "draft": false } });
+        let facts = Facts::new("release", &release);
+        assert_eq!(facts.branch, Some("v1.0.0"));
+        assert_eq!(facts.draft, Some(false));
+    }
+}
@@ -1,13 +1,23 @@
 use crate::builder::ContentBuilder;
-use crate::builder::MessageBuilder;
+use crate::builder::{Message, MessageBuilder};
+use crate::config::{ActivityMode, Target, UnsupportedPolicy};
+use crate::embed::EmbedBuilder;
 use crate::error::MyError;
-use crate::utils::prelude::*;
-use crate::webhook::WebHook;
+use crate::filter::Facts;
+use crate::sink::{Post, Tracking};
+use crate::template::TemplateContext;
+use crate::users::UserMap;
+use crate::utils::{action::Action, git_ref::RefKind, prelude::*};
+use crate::webhook::{self, WebHook};
 use actix_web::{web, HttpRequest, HttpResponse};
 use github_webhook::event::{
-    Event, IssueCommentEvent, IssuesEvent, PullRequestEvent, PullRequestReviewCommentEvent,
-    PullRequestReviewEvent, PushEvent,
+    CreateEvent, DeleteEvent, DiscussionCommentEvent, DiscussionEvent, Event, ForkEvent,
+    IssueCommentEvent, IssuesEvent, PullRequestEvent, PullRequestReviewCommentEvent,
+    PullRequestReviewEvent, PushEvent, ReleaseEvent, StarAction, StarEvent, WorkflowJobAction,
+    WorkflowJobEvent, WorkflowRunAction, WorkflowRunEvent,
 };
+use log::{info, warn};
+use serde_json::{json, Value};
 use std::rc::Rc;
 
 pub async fn webhook(
@@ -19,40 +29,130 @@ pub async fn webhook(
 
     match result {
         Ok(event) => {
-            match event {
-                Event::Issues(e) => issue_handler(&hook, e).await,
-                Event::IssueComment(e) => issue_comment_handler(&hook, e).await,
-                Event::PullRequest(e) => pull_request_handler(&hook, e).await,
-                Event::PullRequestReview(e) => pull_request_review_handler(&hook, e).await,
-                Event::PullRequestReviewComment(e) => {
-                    pull_request_review_comment_handler(&hook, e).await
-                }
-                Event::Push(e) => push_handler(&hook, e).await,
-                Event::Ping(_) => ping_handler().await,
-                _ => unimplemented!(),
+            let response = dispatch(&req, &hook, &body, event).await;
+            if response.is_err() {
+                // so that github can redeliver it
+                hook.forget_delivery(&req);
             }
-
-            // Ok(HttpResponse::Ok().body("correctly parsed"))
+            response
+        }
+        Err(MyError::DuplicateDelivery(delivery)) => {
+            info!("skipped duplicate delivery: {}", delivery);
+            Ok(HttpResponse::Ok().body("already processed"))
         }
         Err(e) => {
-            println!("{}", e);
-            println!("{:?}", e);
-            Ok(HttpResponse::BadRequest().body(e.to_string()))
+            warn!("rejected webhook: {} ({:?})", e, e);
+            Err(e)
+        }
+    }
+}
+
+async fn dispatch(
+    req: &HttpRequest,
+    hook: &WebHook,
+    body: &str,
+    event: Event,
+) -> Result<HttpResponse, MyError> {
+    let event_name = webhook::event_name(req).unwrap_or("unknown");
+    let payload = serde_json::from_str::<Value>(body)?;
+    let facts = Facts::new(event_name, &payload);
+    if !hook.is_allowed(&facts) {
+        info!(
+            "filtered out {} event of {}",
+            event_name,
+            facts.repository.unwrap_or("no repository")
+        );
+        return Ok(HttpResponse::Ok().body("successfully accepted, but filtered out"));
+    }
+
+    // `owner/repo`, which is also the key of tracked messages
+    let targets = facts
+        .repository
+        .map(|repo| hook.targets(repo))
+        .unwrap_or_default();
+    if targets.is_empty() && !matches!(event, Event::Ping(_)) {
+        info!(
+            "no route for {} event of {}",
+            event_name,
+            facts.repository.unwrap_or("no repository")
+        );
+        return Ok(HttpResponse::Ok().body("successfully accepted, but no route"));
+    }
+
+    match event {
+        Event::Issues(e) => issue_handler(hook, &targets, e).await,
+        Event::IssueComment(e) => issue_comment_handler(hook, &targets, e).await,
+        Event::PullRequest(e) => pull_request_handler(hook, &targets, e).await,
+        Event::PullRequestReview(e) => pull_request_review_handler(hook, &targets, e).await,
+        Event::PullRequestReviewComment(e) => {
+            pull_request_review_comment_handler(hook, &targets, e).await
         }
+        Event::Push(e) => push_handler(hook, &targets, e).await,
+        Event::Release(e) => release_handler(hook, &targets, e).await,
+        Event::Create(e) => create_handler(hook, &targets, e).await,
+        Event::Delete(e) => delete_handler(hook, &targets, e).await,
+        Event::Discussion(e) => discussion_handler(hook, &targets, e).await,
+        Event::DiscussionComment(e) => discussion_comment_handler(hook, &targets, e).await,
+        Event::Star(e) => star_handler(hook, &targets, e).await,
+        Event::Fork(e) => fork_handler(hook, &targets, e).await,
+        Event::Watch(_) => watch_handler().await,
+        Event::WorkflowRun(e) => workflow_run_handler(hook, &targets, e).await,
+        Event::WorkflowJob(e) => workflow_job_handler(hook, &targets, facts.branch, e).await,
+        Event::Ping(_) => ping_handler().await,
+        _ => unsupported_handler(hook, &targets, event_name, &payload).await,
     }
 }
 
-async fn issue_handler(hook: &WebHook, event: IssuesEvent) -> Result<HttpResponse, MyError> {
+async fn issue_handler(
+    hook: &WebHook,
+    targets: &[Target],
+    event: IssuesEvent,
+) -> Result<HttpResponse, MyError> {
     let event = Rc::new(EIssues(event));
+    let tracking = tracking(
+        tracking_key(&*event, event.issue.number),
+        event.action(),
+        hook.users(),
+    );
+
+    let data = TemplateContext::new(Rc::clone(&event))
+        .users(hook.users())
+        .issue()
+        .action()
+        .comment(hook.limits())
+        .assignees()
+        .labels()
+        .repo()
+        .build();
+    if let Some(message) = hook.render_template("issues", &data)? {
+        return post_rendered(
+            hook,
+            targets,
+            Post::new(message).data("issues", data).tracking(tracking),
+        );
+    }
 
     let title = ContentBuilder::new(Rc::clone(&event))
+        .users(hook.users())
         .issue()
         .action()
         .build();
 
-    let msg = ContentBuilder::new(Rc::clone(&event)).comment().build();
+    let msg = ContentBuilder::new(Rc::clone(&event))
+        .users(hook.users())
+        .comment(hook.limits())
+        .build();
     let repo = ContentBuilder::new(Rc::clone(&event)).repo().build();
 
+    let embed = EmbedBuilder::new(Rc::clone(&event))
+        .issue()
+        .action()
+        .comment()
+        .assignees()
+        .labels()
+        .repo()
+        .build();
+
     let message = MessageBuilder::new()
         .title(title)
         .msg(msg)
@@ -60,8 +160,14 @@ async fn issue_handler(hook: &WebHook, event: IssuesEvent) -> Result<HttpRespons
         .build();
 
     if let Some(message) = message {
-        let _ = hook.post_message(message.as_ref()).await?;
-        Ok(HttpResponse::Ok().body("successfully posted"))
+        hook.enqueue(
+            targets,
+            Post::new(message.as_ref())
+                .embed(embed)
+                .data("issues", data)
+                .tracking(tracking),
+        )?;
+        Ok(HttpResponse::Ok().body("successfully queued"))
     } else {
         Ok(HttpResponse::Ok().body("successfully accepted, but not posted"))
     }
@@ -69,17 +175,108 @@ async fn issue_handler(hook: &WebHook, event: IssuesEvent) -> Result<HttpRespons
 
 async fn issue_comment_handler(
     hook: &WebHook,
+    targets: &[Target],
     event: IssueCommentEvent,
 ) -> Result<HttpResponse, MyError> {
     let event = Rc::new(EIssueComment(event));
 
+    let data = TemplateContext::new(Rc::clone(&event))
+        .users(hook.users())
+        .issue()
+        .action()
+        .comment(hook.limits())
+        .assignees()
+        .labels()
+        .repo()
+        .build();
+    if let Some(message) = hook.render_template("issue_comment", &data)? {
+        return post_rendered(
+            hook,
+            targets,
+            Post::new(message).data("issue_comment", data),
+        );
+    }
+
     let title = ContentBuilder::new(Rc::clone(&event))
+        .users(hook.users())
         .issue()
         .action()
         .build();
-    let msg = ContentBuilder::new(Rc::clone(&event)).comment().build();
+    let msg = ContentBuilder::new(Rc::clone(&event))
+        .users(hook.users())
+        .comment(hook.limits())
+        .build();
+    let repo = ContentBuilder::new(Rc::clone(&event)).repo().build();
+
+    let embed = EmbedBuilder::new(Rc::clone(&event))
+        .issue()
+        .action()
+        .comment()
+        .assignees()
+        .labels()
+        .repo()
+        .build();
+
+    let message = MessageBuilder::new()
+        .title(title)
+        .msg(msg)
+        .repo(repo)
+        .build();
+
+    if let Some(message) = message {
+        hook.enqueue(
+            targets,
+            Post::new(message.as_ref())
+                .embed(embed)
+                .data("issue_comment", data),
+        )?;
+        Ok(HttpResponse::Ok().body("successfully queued"))
+    } else {
+        Ok(HttpResponse::Ok().body("successfully accepted, but not posted"))
+    }
+}
+
+async fn discussion_handler(
+    hook: &WebHook,
+    targets: &[Target],
+    event: DiscussionEvent,
+) -> Result<HttpResponse, MyError> {
+    let event = Rc::new(EDiscussion(event));
+
+    let data = TemplateContext::new(Rc::clone(&event))
+        .users(hook.users())
+        .discussion()
+        .action()
+        .comment(hook.limits())
+        .labels()
+        .repo()
+        .build();
+    if let Some(message) = hook.render_template("discussion", &data)? {
+        return post_rendered(hook, targets, Post::new(message).data("discussion", data));
+    }
+
+    let title = ContentBuilder::new(Rc::clone(&event))
+        .users(hook.users())
+        .msg("Discussion")
+        .discussion()
+        .action()
+        .build();
+    let msg = ContentBuilder::new(Rc::clone(&event))
+        .users(hook.users())
+        .category()
+        .comment(hook.limits())
+        .labels()
+        .build_lines();
     let repo = ContentBuilder::new(Rc::clone(&event)).repo().build();
 
+    let embed = EmbedBuilder::new(Rc::clone(&event))
+        .discussion()
+        .action()
+        .comment()
+        .labels()
+        .repo()
+        .build();
+
     let message = MessageBuilder::new()
         .title(title)
         .msg(msg)
@@ -87,19 +284,108 @@ async fn issue_comment_handler(
         .build();
 
     if let Some(message) = message {
-        let _ = hook.post_message(message.as_ref()).await?;
-        Ok(HttpResponse::Ok().body("successfully posted"))
+        hook.enqueue(
+            targets,
+            Post::new(message.as_ref())
+                .embed(embed)
+                .data("discussion", data),
+        )?;
+        Ok(HttpResponse::Ok().body("successfully queued"))
     } else {
         Ok(HttpResponse::Ok().body("successfully accepted, but not posted"))
     }
 }
 
-async fn push_handler(hook: &WebHook, event: PushEvent) -> Result<HttpResponse, MyError> {
+async fn discussion_comment_handler(
+    hook: &WebHook,
+    targets: &[Target],
+    event: DiscussionCommentEvent,
+) -> Result<HttpResponse, MyError> {
+    let event = Rc::new(EDiscussionComment(event));
+
+    let data = TemplateContext::new(Rc::clone(&event))
+        .users(hook.users())
+        .discussion()
+        .action()
+        .comment(hook.limits())
+        .labels()
+        .repo()
+        .build();
+    if let Some(message) = hook.render_template("discussion_comment", &data)? {
+        return post_rendered(
+            hook,
+            targets,
+            Post::new(message).data("discussion_comment", data),
+        );
+    }
+
+    let title = ContentBuilder::new(Rc::clone(&event))
+        .users(hook.users())
+        .msg("Discussion")
+        .discussion()
+        .action()
+        .build();
+    let msg = ContentBuilder::new(Rc::clone(&event))
+        .users(hook.users())
+        .category()
+        .comment(hook.limits())
+        .labels()
+        .build_lines();
+    let repo = ContentBuilder::new(Rc::clone(&event)).repo().build();
+
+    let embed = EmbedBuilder::new(Rc::clone(&event))
+        .discussion()
+        .action()
+        .comment()
+        .labels()
+        .repo()
+        .build();
+
+    let message = MessageBuilder::new()
+        .title(title)
+        .msg(msg)
+        .repo(repo)
+        .build();
+
+    if let Some(message) = message {
+        hook.enqueue(
+            targets,
+            Post::new(message.as_ref())
+                .embed(embed)
+                .data("discussion_comment", data),
+        )?;
+        Ok(HttpResponse::Ok().body("successfully queued"))
+    } else {
+        Ok(HttpResponse::Ok().body("successfully accepted, but not posted"))
+    }
+}
+
+async fn push_handler(
+    hook: &WebHook,
+    targets: &[Target],
+    event: PushEvent,
+) -> Result<HttpResponse, MyError> {
     let event = Rc::new(EPush(event));
 
-    let title = ContentBuilder::new(Rc::clone(&event)).action().build();
+    let data = TemplateContext::new(Rc::clone(&event))
+        .action()
+        .git_ref()
+        .commits()
+        .var("compare", &event.compare)
+        .var("forced", event.forced)
+        .repo()
+        .build();
+    if let Some(message) = hook.render_template("push", &data)? {
+        return post_rendered(hook, targets, Post::new(message).data("push", data));
+    }
+
+    let title = ContentBuilder::new(Rc::clone(&event))
+        .users(hook.users())
+        .action()
+        .build();
     let msg = ContentBuilder::new(Rc::clone(&event))
-        .commit()
+        .users(hook.users())
+        .commit(hook.limits().commits)
         .build_lines();
     let repo = ContentBuilder::new(Rc::clone(&event)).repo().build();
 
@@ -110,8 +396,8 @@ async fn push_handler(hook: &WebHook, event: PushEvent) -> Result<HttpResponse,
         .build();
 
     if let Some(message) = message {
-        let _ = hook.post_message(message.as_ref()).await?;
-        Ok(HttpResponse::Ok().body("successfully posted"))
+        hook.enqueue(targets, Post::new(message.as_ref()).data("push", data))?;
+        Ok(HttpResponse::Ok().body("successfully queued"))
     } else {
         Ok(HttpResponse::Ok().body("successfully accepted, but not posted"))
     }
@@ -119,22 +405,59 @@ async fn push_handler(hook: &WebHook, event: PushEvent) -> Result<HttpResponse,
 
 async fn pull_request_handler(
     hook: &WebHook,
+    targets: &[Target],
     event: PullRequestEvent,
 ) -> Result<HttpResponse, MyError> {
     let event = Rc::new(EPullRequest(event));
+    let tracking = tracking(
+        tracking_key(&*event, event.pull_request.number),
+        event.action(),
+        hook.users(),
+    );
+
+    let data = TemplateContext::new(Rc::clone(&event))
+        .users(hook.users())
+        .pr()
+        .action()
+        .comment(hook.limits())
+        .assignees()
+        .labels()
+        .repo()
+        .build();
+    if let Some(message) = hook.render_template("pull_request", &data)? {
+        return post_rendered(
+            hook,
+            targets,
+            Post::new(message)
+                .data("pull_request", data)
+                .tracking(tracking),
+        );
+    }
 
     let title = ContentBuilder::new(Rc::clone(&event))
+        .users(hook.users())
         .msg("Pull Request")
         .pr()
         .action()
         .build();
     let msg = ContentBuilder::new(Rc::clone(&event))
-        .comment()
+        .users(hook.users())
+        .pr_details()
+        .comment(hook.limits())
         .assignees()
         .labels()
         .build_lines();
     let repo = ContentBuilder::new(Rc::clone(&event)).repo().build();
 
+    let embed = EmbedBuilder::new(Rc::clone(&event))
+        .pr()
+        .action()
+        .comment()
+        .assignees()
+        .labels()
+        .repo()
+        .build();
+
     let message = MessageBuilder::new()
         .title(title)
         .msg(msg)
@@ -142,8 +465,14 @@ async fn pull_request_handler(
         .build();
 
     if let Some(message) = message {
-        let _ = hook.post_message(message.as_ref()).await?;
-        Ok(HttpResponse::Ok().body("successfully posted"))
+        hook.enqueue(
+            targets,
+            Post::new(message.as_ref())
+                .embed(embed)
+                .data("pull_request", data)
+                .tracking(tracking),
+        )?;
+        Ok(HttpResponse::Ok().body("successfully queued"))
     } else {
         Ok(HttpResponse::Ok().body("successfully accepted, but not posted"))
     }
@@ -151,25 +480,64 @@ async fn pull_request_handler(
 
 async fn pull_request_review_handler(
     hook: &WebHook,
+    targets: &[Target],
     event: PullRequestReviewEvent,
 ) -> Result<HttpResponse, MyError> {
     match event.review.state.as_str() {
         "approved" | "commented" | "changes_requested" => {
             let event = Rc::new(EPullRequestReview(event));
+            let review = match event.review.state.as_str() {
+                "approved" => "Approved",
+                "changes_requested" => "Changes requested",
+                _ => "Reviewed",
+            };
+            let tracking =
+                tracking_key(&*event, event.pull_request.number).map(|key| Tracking::Status {
+                    key,
+                    status: format!("{} by {}", review, hook.users().name(&event.sender.login)),
+                });
+
+            let data = TemplateContext::new(Rc::clone(&event))
+                .users(hook.users())
+                .pr()
+                .action()
+                .comment(hook.limits())
+                .assignees()
+                .repo()
+                .build();
+            if let Some(message) = hook.render_template("pull_request_review", &data)? {
+                return post_rendered(
+                    hook,
+                    targets,
+                    Post::new(message)
+                        .data("pull_request_review", data)
+                        .tracking(tracking),
+                );
+            }
 
             let title = ContentBuilder::new(Rc::clone(&event))
+                .users(hook.users())
                 .msg("Pull Request")
                 .pr()
                 .action()
                 .build();
 
             let msg = ContentBuilder::new(Rc::clone(&event))
-                .comment()
+                .users(hook.users())
+                .comment(hook.limits())
                 .assignees()
                 .build_lines();
 
             let repo = ContentBuilder::new(Rc::clone(&event)).repo().build();
 
+            let embed = EmbedBuilder::new(Rc::clone(&event))
+                .pr()
+                .action()
+                .comment()
+                .assignees()
+                .repo()
+                .build();
+
             let message = MessageBuilder::new()
                 .title(title)
                 .msg(msg)
@@ -177,8 +545,14 @@ async fn pull_request_review_handler(
                 .build();
 
             if let Some(message) = message {
-                let _ = hook.post_message(message.as_ref()).await?;
-                Ok(HttpResponse::Ok().body("successfully posted"))
+                hook.enqueue(
+                    targets,
+                    Post::new(message.as_ref())
+                        .embed(embed)
+                        .data("pull_request_review", data)
+                        .tracking(tracking),
+                )?;
+                Ok(HttpResponse::Ok().body("successfully queued"))
             } else {
                 Ok(HttpResponse::Ok().body("successfully accepted, but not posted"))
             }
@@ -189,17 +563,241 @@ async fn pull_request_review_handler(
 
 async fn pull_request_review_comment_handler(
     hook: &WebHook,
+    targets: &[Target],
     event: PullRequestReviewCommentEvent,
 ) -> Result<HttpResponse, MyError> {
     let event = Rc::new(EPullRequestReviewComment(event));
 
+    let data = TemplateContext::new(Rc::clone(&event))
+        .users(hook.users())
+        .pr()
+        .review(hook.limits())
+        .action()
+        .comment(hook.limits())
+        .assignees()
+        .repo()
+        .build();
+    if let Some(message) = hook.render_template("pull_request_review_comment", &data)? {
+        return post_rendered(
+            hook,
+            targets,
+            Post::new(message).data("pull_request_review_comment", data),
+        );
+    }
+
     let title = ContentBuilder::new(Rc::clone(&event))
+        .users(hook.users())
         .review_md()
         .action()
         .build();
     let msg = ContentBuilder::new(Rc::clone(&event))
+        .users(hook.users())
+        .comment(hook.limits())
+        .assignees()
+        .build_lines();
+    let repo = ContentBuilder::new(Rc::clone(&event)).repo().build();
+
+    let embed = EmbedBuilder::new(Rc::clone(&event))
+        .pr()
+        .action()
         .comment()
         .assignees()
+        .repo()
+        .build();
+
+    let message = MessageBuilder::new()
+        .title(title)
+        .msg(msg)
+        .repo(repo)
+        .build();
+
+    if let Some(message) = message {
+        hook.enqueue(
+            targets,
+            Post::new(message.as_ref())
+                .embed(embed)
+                .data("pull_request_review_comment", data),
+        )?;
+        Ok(HttpResponse::Ok().body("successfully queued"))
+    } else {
+        Ok(HttpResponse::Ok().body("successfully accepted, but not posted"))
+    }
+}
+
+async fn release_handler(
+    hook: &WebHook,
+    targets: &[Target],
+    event: ReleaseEvent,
+) -> Result<HttpResponse, MyError> {
+    let event = Rc::new(ERelease(event));
+
+    let data = TemplateContext::new(Rc::clone(&event))
+        .users(hook.users())
+        .release(hook.limits())
+        .action()
+        .repo()
+        .build();
+    if let Some(message) = hook.render_template("release", &data)? {
+        return post_rendered(hook, targets, Post::new(message).data("release", data));
+    }
+
+    let title = ContentBuilder::new(Rc::clone(&event))
+        .users(hook.users())
+        .msg(if event.release.prerelease {
+            "Pre-release"
+        } else {
+            "Release"
+        })
+        .release()
+        .action()
+        .build();
+    let msg = ContentBuilder::new(Rc::clone(&event))
+        .users(hook.users())
+        .release_body(hook.limits())
+        .build_lines();
+    let repo = ContentBuilder::new(Rc::clone(&event)).repo().build();
+
+    let message = MessageBuilder::new()
+        .title(title)
+        .msg(msg)
+        .repo(repo)
+        .build();
+
+    if let Some(message) = message {
+        hook.enqueue(targets, Post::new(message.as_ref()).data("release", data))?;
+        Ok(HttpResponse::Ok().body("successfully queued"))
+    } else {
+        Ok(HttpResponse::Ok().body("successfully accepted, but not posted"))
+    }
+}
+
+async fn create_handler(
+    hook: &WebHook,
+    targets: &[Target],
+    event: CreateEvent,
+) -> Result<HttpResponse, MyError> {
+    let event = Rc::new(ECreate(event));
+    // branches are posted by `push_handler`, which github sends together with this event
+    if matches!(event.git_ref(), Some(git_ref) if git_ref.kind() == RefKind::Branch) {
+        return Ok(HttpResponse::Ok().body("successfully accepted, but not posted"));
+    }
+
+    let data = TemplateContext::new(Rc::clone(&event))
+        .git_ref()
+        .action()
+        .repo()
+        .build();
+    if let Some(message) = hook.render_template("create", &data)? {
+        return post_rendered(hook, targets, Post::new(message).data("create", data));
+    }
+
+    let title = ContentBuilder::new(Rc::clone(&event))
+        .users(hook.users())
+        .git_ref()
+        .action()
+        .build();
+    let repo = ContentBuilder::new(Rc::clone(&event)).repo().build();
+
+    let message = MessageBuilder::new().title(title).repo(repo).build();
+
+    if let Some(message) = message {
+        hook.enqueue(targets, Post::new(message.as_ref()).data("create", data))?;
+        Ok(HttpResponse::Ok().body("successfully queued"))
+    } else {
+        Ok(HttpResponse::Ok().body("successfully accepted, but not posted"))
+    }
+}
+
+async fn delete_handler(
+    hook: &WebHook,
+    targets: &[Target],
+    event: DeleteEvent,
+) -> Result<HttpResponse, MyError> {
+    let event = Rc::new(EDelete(event));
+    // branches are posted by `push_handler`, which github sends together with this event
+    if matches!(event.git_ref(), Some(git_ref) if git_ref.kind() == RefKind::Branch) {
+        return Ok(HttpResponse::Ok().body("successfully accepted, but not posted"));
+    }
+
+    let data = TemplateContext::new(Rc::clone(&event))
+        .git_ref()
+        .action()
+        .repo()
+        .build();
+    if let Some(message) = hook.render_template("delete", &data)? {
+        return post_rendered(hook, targets, Post::new(message).data("delete", data));
+    }
+
+    let title = ContentBuilder::new(Rc::clone(&event))
+        .users(hook.users())
+        .git_ref()
+        .action()
+        .build();
+    let repo = ContentBuilder::new(Rc::clone(&event)).repo().build();
+
+    let message = MessageBuilder::new().title(title).repo(repo).build();
+
+    if let Some(message) = message {
+        hook.enqueue(targets, Post::new(message.as_ref()).data("delete", data))?;
+        Ok(HttpResponse::Ok().body("successfully queued"))
+    } else {
+        Ok(HttpResponse::Ok().body("successfully accepted, but not posted"))
+    }
+}
+
+async fn workflow_run_handler(
+    hook: &WebHook,
+    targets: &[Target],
+    event: WorkflowRunEvent,
+) -> Result<HttpResponse, MyError> {
+    let not_posted = Ok(HttpResponse::Ok().body("successfully accepted, but not posted"));
+
+    if !matches!(event.action, WorkflowRunAction::Completed) {
+        return not_posted;
+    }
+
+    let repo = format!("{}/{}", event.repository.owner.login, event.repository.name);
+    let branch = event.workflow_run.head_branch.clone();
+    let watched = hook
+        .route(&repo)
+        .map(|r| r.workflow.watches(&branch))
+        .unwrap_or(false);
+    if !watched {
+        return not_posted;
+    }
+
+    let event = Rc::new(EWorkflowRun(event));
+    let workflow = match event.workflow() {
+        Some(w) => w,
+        None => return not_posted,
+    };
+
+    // a success is only posted when it follows a failure on the same workflow and branch.
+    let key = format!("{}/{}/{}", repo, event.workflow_run.name, branch);
+    if workflow.is_failure() {
+        hook.record_workflow(&key, true);
+    } else if !(workflow.is_success() && hook.record_workflow(&key, false)) {
+        return not_posted;
+    }
+
+    let data = TemplateContext::new(Rc::clone(&event))
+        .workflow()
+        .action()
+        .repo()
+        .build();
+    if let Some(message) = hook.render_template("workflow_run", &data)? {
+        return post_rendered(hook, targets, Post::new(message).data("workflow_run", data));
+    }
+
+    let title = ContentBuilder::new(Rc::clone(&event))
+        .users(hook.users())
+        .msg("Workflow")
+        .workflow()
+        .action()
+        .build();
+    let msg = ContentBuilder::new(Rc::clone(&event))
+        .users(hook.users())
+        .head_commit()
         .build_lines();
     let repo = ContentBuilder::new(Rc::clone(&event)).repo().build();
 
@@ -210,13 +808,330 @@ async fn pull_request_review_comment_handler(
         .build();
 
     if let Some(message) = message {
-        let _ = hook.post_message(message.as_ref()).await?;
-        Ok(HttpResponse::Ok().body("successfully posted"))
+        hook.enqueue(
+            targets,
+            Post::new(message.as_ref()).data("workflow_run", data),
+        )?;
+        Ok(HttpResponse::Ok().body("successfully queued"))
+    } else {
+        not_posted
+    }
+}
+
+/// `branch` is the `head_branch` of the job, read from the payload like the filters do.
+async fn workflow_job_handler(
+    hook: &WebHook,
+    targets: &[Target],
+    branch: Option<&str>,
+    event: WorkflowJobEvent,
+) -> Result<HttpResponse, MyError> {
+    let not_posted = Ok(HttpResponse::Ok().body("successfully accepted, but not posted"));
+
+    if !matches!(event.action, WorkflowJobAction::Completed) {
+        return not_posted;
+    }
+
+    let repo = format!("{}/{}", event.repository.owner.login, event.repository.name);
+    let enabled = hook
+        .route(&repo)
+        .map(|r| r.workflow.jobs && r.workflow.watches(branch.unwrap_or_default()))
+        .unwrap_or(false);
+    if !enabled {
+        return not_posted;
+    }
+
+    let event = Rc::new(EWorkflowJob(event));
+    if !event.workflow().map(|w| w.is_failure()).unwrap_or(false) {
+        return not_posted;
+    }
+
+    let data = TemplateContext::new(Rc::clone(&event))
+        .workflow()
+        .action()
+        .repo()
+        .build();
+    if let Some(message) = hook.render_template("workflow_job", &data)? {
+        return post_rendered(hook, targets, Post::new(message).data("workflow_job", data));
+    }
+
+    let title = ContentBuilder::new(Rc::clone(&event))
+        .users(hook.users())
+        .msg("Job")
+        .workflow()
+        .action()
+        .build();
+    let msg = ContentBuilder::new(Rc::clone(&event))
+        .users(hook.users())
+        .head_commit()
+        .build_lines();
+    let repo = ContentBuilder::new(Rc::clone(&event)).repo().build();
+
+    let message = MessageBuilder::new()
+        .title(title)
+        .msg(msg)
+        .repo(repo)
+        .build();
+
+    if let Some(message) = message {
+        hook.enqueue(
+            targets,
+            Post::new(message.as_ref()).data("workflow_job", data),
+        )?;
+        Ok(HttpResponse::Ok().body("successfully queued"))
+    } else {
+        not_posted
+    }
+}
+
+fn activity_mode(hook: &WebHook, repository: &github_webhook::event::Repository) -> ActivityMode {
+    let repo = format!("{}/{}", repository.owner.login, repository.name);
+    hook.route(&repo).map(|r| r.activity).unwrap_or_default()
+}
+
+async fn star_handler(
+    hook: &WebHook,
+    targets: &[Target],
+    event: StarEvent,
+) -> Result<HttpResponse, MyError> {
+    match activity_mode(hook, &event.repository) {
+        ActivityMode::Every => {
+            let event = Rc::new(EStar(event));
+            let data = TemplateContext::new(Rc::clone(&event))
+                .action()
+                .repo()
+                .build();
+            if let Some(message) = hook.render_template("star", &data)? {
+                return post_rendered(hook, targets, Post::new(message).data("star", data));
+            }
+
+            let title = ContentBuilder::new(Rc::clone(&event))
+                .users(hook.users())
+                .action()
+                .build();
+            let repo = ContentBuilder::new(Rc::clone(&event)).repo().build();
+            activity_post(hook, targets, title, repo, Some(("star", data))).await
+        }
+        ActivityMode::Milestone => {
+            if let StarAction::Deleted = event.action {
+                return activity_post(hook, targets, None, None, None).await;
+            }
+            let key = format!("{}/stars", event.repository.html_url);
+            let milestone = match hook.reach_milestone(&key, event.repository.stargazers_count) {
+                Some(milestone) => milestone,
+                None => return activity_post(hook, targets, None, None, None).await,
+            };
+
+            let event = Rc::new(EStar(event));
+            let data = TemplateContext::new(Rc::clone(&event))
+                .repo()
+                .var("milestone", milestone)
+                .build();
+            if let Some(message) = hook.render_template("star_milestone", &data)? {
+                return post_rendered(hook, targets, Post::new(message).data("star", data));
+            }
+
+            let title = Some(format!("Reached {} stars :tada:", milestone));
+            let repo = ContentBuilder::new(event).repo().build();
+            activity_post(hook, targets, title, repo, Some(("star", data))).await
+        }
+        ActivityMode::Off => activity_post(hook, targets, None, None, None).await,
+    }
+}
+
+async fn fork_handler(
+    hook: &WebHook,
+    targets: &[Target],
+    event: ForkEvent,
+) -> Result<HttpResponse, MyError> {
+    match activity_mode(hook, &event.repository) {
+        ActivityMode::Every => {
+            let event = Rc::new(EFork(event));
+            let data = TemplateContext::new(Rc::clone(&event))
+                .action()
+                .repo()
+                .build();
+            if let Some(message) = hook.render_template("fork", &data)? {
+                return post_rendered(hook, targets, Post::new(message).data("fork", data));
+            }
+
+            let title = ContentBuilder::new(Rc::clone(&event))
+                .users(hook.users())
+                .action()
+                .build();
+            let repo = ContentBuilder::new(Rc::clone(&event)).repo().build();
+            activity_post(hook, targets, title, repo, Some(("fork", data))).await
+        }
+        ActivityMode::Milestone => {
+            let key = format!("{}/forks", event.repository.html_url);
+            let milestone = match hook.reach_milestone(&key, event.repository.forks_count) {
+                Some(milestone) => milestone,
+                None => return activity_post(hook, targets, None, None, None).await,
+            };
+
+            let event = Rc::new(EFork(event));
+            let data = TemplateContext::new(Rc::clone(&event))
+                .repo()
+                .var("milestone", milestone)
+                .build();
+            if let Some(message) = hook.render_template("fork_milestone", &data)? {
+                return post_rendered(hook, targets, Post::new(message).data("fork", data));
+            }
+
+            let title = Some(format!("Reached {} forks :tada:", milestone));
+            let repo = ContentBuilder::new(event).repo().build();
+            activity_post(hook, targets, title, repo, Some(("fork", data))).await
+        }
+        ActivityMode::Off => activity_post(hook, targets, None, None, None).await,
+    }
+}
+
+/// github sends a `watch` event together with every `star` event,
+/// so it is never posted.
+async fn watch_handler() -> Result<HttpResponse, MyError> {
+    Ok(HttpResponse::Ok().body("successfully accepted, but not posted"))
+}
+
+async fn activity_post(
+    hook: &WebHook,
+    targets: &[Target],
+    title: Option<String>,
+    repo: Option<String>,
+    data: Option<(&str, Value)>,
+) -> Result<HttpResponse, MyError> {
+    let message = MessageBuilder::new().title(title).repo(repo).build();
+
+    if let Some(message) = message {
+        let mut post = Post::new(message.as_ref());
+        if let Some((event, data)) = data {
+            post = post.data(event, data);
+        }
+        hook.enqueue(targets, post)?;
+        Ok(HttpResponse::Ok().body("successfully queued"))
     } else {
         Ok(HttpResponse::Ok().body("successfully accepted, but not posted"))
     }
 }
 
+/// `owner/repo#num` of an issue or a pull request, with the repository named
+/// the same way as in routes.
+fn tracking_key(event: &impl TRepository, num: u64) -> Option<String> {
+    event
+        .repo()
+        .map(|repo| format!("{}#{}", repo.full_name(), num))
+}
+
+/// how an event about the issue or pull request `key` changes the message posted for it.
+fn tracking(key: Option<String>, action: Option<Action>, users: &UserMap) -> Option<Tracking> {
+    let (key, action) = (key?, action?);
+    let tracking = match action.key() {
+        "opened" | "edited" => Tracking::Post { key },
+        "deleted" => Tracking::Delete { key },
+        _ => Tracking::Status {
+            key,
+            status: action.md(users),
+        },
+    };
+    Some(tracking)
+}
+
+/// queues a message rendered from a user template. a blank message is not posted.
+fn post_rendered(hook: &WebHook, targets: &[Target], post: Post) -> Result<HttpResponse, MyError> {
+    if post.message.trim().is_empty() {
+        Ok(HttpResponse::Ok().body("successfully accepted, but not posted"))
+    } else {
+        hook.enqueue(targets, post)?;
+        Ok(HttpResponse::Ok().body("successfully queued"))
+    }
+}
+
 async fn ping_handler() -> Result<HttpResponse, MyError> {
     Ok(HttpResponse::Ok().body("pong!"))
 }
+
+/// events which have no handler are acknowledged with 202 and never posted,
+/// unless `unsupported = "fallback"` is set in the config.
+async fn unsupported_handler(
+    hook: &WebHook,
+    targets: &[Target],
+    event_name: &str,
+    payload: &Value,
+) -> Result<HttpResponse, MyError> {
+    info!("ignored unsupported event: {}", event_name);
+
+    if let UnsupportedPolicy::Fallback = hook.unsupported_policy() {
+        if let Some(message) = fallback_message(event_name, payload) {
+            hook.enqueue_message(targets, message.as_ref())?;
+        }
+    }
+
+    Ok(HttpResponse::Accepted().json(json!({ "message": format!("ignored: {}", event_name) })))
+}
+
+/// generic message built from the raw payload of an unsupported event.
+fn fallback_message(event_name: &str, payload: &Value) -> Option<Message> {
+    let title = match (
+        payload["action"].as_str(),
+        payload["sender"]["login"].as_str(),
+    ) {
+        (Some(action), Some(sender)) => format!("`{}` {} by `{}`", event_name, action, sender),
+        (None, Some(sender)) => format!("`{}` by `{}`", event_name, sender),
+        (_, None) => format!("`{}`", event_name),
+    };
+    let repo = match (
+        payload["repository"]["full_name"].as_str(),
+        payload["repository"]["html_url"].as_str(),
+    ) {
+        (Some(name), Some(url)) => Some(format!("[{}]({})", name, url)),
+        _ => None,
+    };
+
+    MessageBuilder::new().title(Some(title)).repo(repo).build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, GithubSecret, TraqTarget};
+    use crate::sink::MemorySink;
+    use std::fs;
+
+    fn hook(name: &str, sink: &MemorySink) -> WebHook {
+        let dir =
+            std::env::temp_dir().join(format!("portfolio_webhook-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut config = Config::single(Target::Traq(TraqTarget::new("id", "secret")));
+        config.github.secrets.push(GithubSecret::new("secret"));
+        config.outbox.path = dir.join("outbox.jsonl");
+        config.store.path = dir.join("messages.json");
+        WebHook::new(config).unwrap().with_sink(sink.clone())
+    }
+
+    /// sends the queued posts to the sink of `hook`.
+    async fn deliver(hook: &WebHook) {
+        for entry in hook.outbox().due().unwrap() {
+            hook.deliver(&entry.target, &entry.post).await.unwrap();
+            hook.outbox().delivered(entry.id).unwrap();
+        }
+    }
+
+    #[actix_rt::test]
+    async fn tracks_issue_by_the_name_routes_use() {
+        let sink = MemorySink::new();
+        let hook = hook("tracking", &sink);
+        let payload: Value =
+            serde_json::from_str(include_str!("../tests/fixtures/issues_opened.json")).unwrap();
+        let event: IssuesEvent = serde_json::from_value(payload.clone()).unwrap();
+        let repository = Facts::new("issues", &payload).repository.unwrap();
+
+        let targets = hook.targets(repository);
+        issue_handler(&hook, &targets, event).await.unwrap();
+        deliver(&hook).await;
+
+        let posts = sink.posts();
+        assert_eq!(posts.len(), 1);
+        let tracking = posts[0].tracking.as_ref().expect("the issue is tracked");
+        assert_eq!(tracking.key(), format!("{}#1", repository));
+    }
+}
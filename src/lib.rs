@@ -1,5 +1,15 @@
 pub mod builder;
+pub mod config;
+pub mod delivery;
+pub mod embed;
 pub mod error;
+pub mod filter;
 pub mod handler;
+pub mod outbox;
+pub mod sink;
+pub mod store;
+pub mod template;
+pub mod text;
+pub mod users;
 pub mod utils;
 pub mod webhook;
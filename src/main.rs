@@ -1,30 +1,55 @@
 use std::env;
 
 use actix_web::{web, App, HttpServer};
+use portfolio_webhook::config::{Config, GithubSecret, OutboxConfig, Target, TraqTarget};
 use portfolio_webhook::handler;
+use portfolio_webhook::outbox::{self, Outbox};
 use portfolio_webhook::webhook::WebHook;
 
 const ENV_TRAQ_WEBHOOK_ID: &str = "TRAQ_WEBHOOK_ID";
 const ENV_TRAQ_WEBHOOK_SECRET: &str = "TRAQ_WEBHOOK_SECRET";
+const ENV_TRAQ_BASE_URL: &str = "TRAQ_BASE_URL";
 const ENV_GITHUB_WEBHOOK_SECRET: &str = "GITHUB_WEBHOOK_SECRET";
 const ENV_PORT: &str = "PORT";
+const ENV_CONFIG_PATH: &str = "CONFIG_PATH";
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     env::set_var("RUST_LOG", "info");
     env_logger::init();
 
-    let traq_webhook_id = env::var(ENV_TRAQ_WEBHOOK_ID)
-        .expect(&format!("{} is must not be empty", ENV_TRAQ_WEBHOOK_ID));
-    let traq_webhook_secret = env::var(ENV_TRAQ_WEBHOOK_SECRET)
-        .expect(&format!("{} is must not be empty", ENV_TRAQ_WEBHOOK_SECRET));
-    let github_webhook_secret = env::var(ENV_GITHUB_WEBHOOK_SECRET).expect(&format!(
-        "{} is must not be empty",
-        ENV_GITHUB_WEBHOOK_SECRET
-    ));
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("outbox") {
+        outbox_command(&args[1..]);
+        return Ok(());
+    }
+
+    let mut config = match env::var(ENV_CONFIG_PATH) {
+        Ok(path) => Config::from_file(&path)
+            .unwrap_or_else(|e| panic!("failed to load config {}: {:?}", path, e)),
+        Err(_) => {
+            let traq_webhook_id = env::var(ENV_TRAQ_WEBHOOK_ID)
+                .expect(&format!("{} is must not be empty", ENV_TRAQ_WEBHOOK_ID));
+            let traq_webhook_secret = env::var(ENV_TRAQ_WEBHOOK_SECRET)
+                .expect(&format!("{} is must not be empty", ENV_TRAQ_WEBHOOK_SECRET));
+            let mut target = TraqTarget::new(traq_webhook_id, traq_webhook_secret);
+            if let Ok(base_url) = env::var(ENV_TRAQ_BASE_URL) {
+                target.base_url = base_url;
+            }
+            Config::single(Target::Traq(target))
+        }
+    };
+    // the secret from the environment is accepted in addition to the configured ones
+    if let Ok(github_webhook_secret) = env::var(ENV_GITHUB_WEBHOOK_SECRET) {
+        config
+            .github
+            .secrets
+            .push(GithubSecret::new(github_webhook_secret));
+    }
     let port = env::var(ENV_PORT).expect(&format!("{} is must not be empty", ENV_PORT));
 
-    let data = WebHook::new(github_webhook_secret, traq_webhook_secret, traq_webhook_id);
+    let data = WebHook::new(config).expect("failed to set up webhook");
+    actix_web::rt::spawn(outbox::run(data.clone()));
 
     let addr = format!("0.0.0.0:{}", port);
     HttpServer::new(move || {
@@ -37,3 +62,57 @@ async fn main() -> std::io::Result<()> {
     .run()
     .await
 }
+
+/// `outbox dead` lists dead letters,
+/// `outbox replay <id>...` / `outbox replay --all` queues them again.
+fn outbox_command(args: &[String]) {
+    let config = match env::var(ENV_CONFIG_PATH) {
+        Ok(path) => {
+            Config::from_file(&path)
+                .unwrap_or_else(|e| panic!("failed to load config {}: {:?}", path, e))
+                .outbox
+        }
+        Err(_) => OutboxConfig::default(),
+    };
+    let outbox = Outbox::open(&config).expect("failed to open outbox");
+
+    match args.first().map(String::as_str) {
+        Some("dead") => {
+            for entry in outbox.dead_letters().expect("failed to read outbox") {
+                println!(
+                    "{}\tattempts: {}\ttarget: {}\terror: {}",
+                    entry.id,
+                    entry.attempts,
+                    entry.target,
+                    entry.last_error.unwrap_or_default()
+                );
+                for line in entry.post.message.lines() {
+                    println!("    {}", line);
+                }
+            }
+        }
+        Some("replay") => {
+            let ids: Vec<u64> = if args.get(1).map(String::as_str) == Some("--all") {
+                outbox
+                    .dead_letters()
+                    .expect("failed to read outbox")
+                    .iter()
+                    .map(|e| e.id)
+                    .collect()
+            } else {
+                args[1..]
+                    .iter()
+                    .map(|id| id.parse().expect("id must be a number"))
+                    .collect()
+            };
+            for id in ids {
+                if outbox.replay(id).expect("failed to write outbox") {
+                    println!("{} queued", id);
+                } else {
+                    println!("{} is not a dead letter", id);
+                }
+            }
+        }
+        _ => eprintln!("usage: portfolio_webhook outbox (dead | replay (<id>... | --all))"),
+    }
+}
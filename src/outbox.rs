@@ -0,0 +1,400 @@
+use crate::config::{OutboxConfig, Target};
+use crate::error::MyError;
+use crate::sink::Post;
+use crate::webhook::WebHook;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// a post waiting to be delivered to `target`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: u64,
+    pub target: Target,
+    #[serde(flatten)]
+    pub post: Post,
+    pub attempts: u32,
+    pub next_attempt_at: u64,
+    pub last_error: Option<String>,
+}
+
+/// one line of the outbox log.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Record {
+    Enqueued {
+        entry: Box<Entry>,
+    },
+    Failed {
+        id: u64,
+        attempts: u32,
+        next_attempt_at: u64,
+        error: String,
+    },
+    Delivered {
+        id: u64,
+    },
+    Dead {
+        id: u64,
+        /// missing in logs written before it was recorded.
+        #[serde(default)]
+        attempts: u32,
+        error: String,
+    },
+    Replayed {
+        id: u64,
+    },
+}
+
+#[derive(Default)]
+struct State {
+    offset: u64,
+    next_id: u64,
+    pending: BTreeMap<u64, Entry>,
+    dead: BTreeMap<u64, Entry>,
+}
+
+impl State {
+    fn apply(&mut self, record: Record) {
+        match record {
+            Record::Enqueued { entry } => {
+                self.next_id = self.next_id.max(entry.id + 1);
+                self.pending.insert(entry.id, *entry);
+            }
+            Record::Failed {
+                id,
+                attempts,
+                next_attempt_at,
+                error,
+            } => {
+                if let Some(entry) = self.pending.get_mut(&id) {
+                    entry.attempts = attempts;
+                    entry.next_attempt_at = next_attempt_at;
+                    entry.last_error = Some(error);
+                }
+            }
+            Record::Delivered { id } => {
+                self.pending.remove(&id);
+            }
+            Record::Dead {
+                id,
+                attempts,
+                error,
+            } => {
+                if let Some(mut entry) = self.pending.remove(&id) {
+                    entry.attempts = entry.attempts.max(attempts);
+                    entry.last_error = Some(error);
+                    self.dead.insert(id, entry);
+                }
+            }
+            Record::Replayed { id } => {
+                if let Some(mut entry) = self.dead.remove(&id) {
+                    entry.attempts = 0;
+                    entry.next_attempt_at = 0;
+                    self.pending.insert(id, entry);
+                }
+            }
+        }
+    }
+}
+
+/// append-only log of messages which have to be delivered.
+///
+/// every change is appended to the file first and then read back,
+/// so records written by another process (e.g. `outbox replay`) are picked up too.
+pub struct Outbox {
+    path: PathBuf,
+    config: OutboxConfig,
+    state: Mutex<State>,
+}
+
+impl Outbox {
+    pub fn open(config: &OutboxConfig) -> Result<Self, MyError> {
+        let outbox = Self {
+            path: config.path.clone(),
+            config: config.clone(),
+            state: Mutex::new(State::default()),
+        };
+        outbox.sync(&mut outbox.lock())?;
+        Ok(outbox)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("poisoned lock")
+    }
+
+    /// applies records appended since the last call.
+    fn sync(&self, state: &mut State) -> Result<(), MyError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(&self.path)?;
+        file.seek(SeekFrom::Start(state.offset))?;
+
+        let mut reader = BufReader::new(file);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let len = reader.read_line(&mut line)?;
+            // a line without a newline is still being written
+            if len == 0 || !line.ends_with('\n') {
+                break;
+            }
+            state.offset += len as u64;
+            match serde_json::from_str::<Record>(&line) {
+                Ok(record) => state.apply(record),
+                Err(e) => warn!("skipped broken outbox record: {}", e),
+            }
+        }
+        Ok(())
+    }
+
+    /// appends `records` with one write, so that they cost one sync to the disk.
+    fn append(&self, state: &mut State, records: &[Record]) -> Result<(), MyError> {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path)?;
+        let mut lines = String::new();
+        for record in records {
+            lines += &serde_json::to_string(record)?;
+            lines.push('\n');
+        }
+        file.write_all(lines.as_bytes())?;
+        file.sync_data()?;
+        self.sync(state)
+    }
+
+    /// rewrites the log so that it only holds undelivered entries.
+    pub fn compact(&self) -> Result<(), MyError> {
+        let mut state = self.lock();
+        self.sync(&mut state)?;
+
+        let tmp = self.path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        let mut write = |record: &Record| -> Result<(), MyError> {
+            let line = serde_json::to_string(record)? + "\n";
+            file.write_all(line.as_bytes())?;
+            Ok(())
+        };
+        for entry in state.pending.values().chain(state.dead.values()) {
+            write(&Record::Enqueued {
+                entry: Box::new(entry.clone()),
+            })?;
+        }
+        for entry in state.dead.values() {
+            write(&Record::Dead {
+                id: entry.id,
+                attempts: entry.attempts,
+                error: entry.last_error.clone().unwrap_or_default(),
+            })?;
+        }
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+
+        state.offset = fs::metadata(&self.path)?.len();
+        Ok(())
+    }
+
+    /// queues `post` for each of `targets` and returns the ids of the entries.
+    pub fn enqueue(&self, targets: &[Target], post: &Post) -> Result<Vec<u64>, MyError> {
+        if targets.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut state = self.lock();
+        self.sync(&mut state)?;
+        let ids: Vec<u64> = (state.next_id..).take(targets.len()).collect();
+        let records: Vec<Record> = ids
+            .iter()
+            .zip(targets)
+            .map(|(&id, target)| Record::Enqueued {
+                entry: Box::new(Entry {
+                    id,
+                    target: target.clone(),
+                    post: post.clone(),
+                    attempts: 0,
+                    next_attempt_at: 0,
+                    last_error: None,
+                }),
+            })
+            .collect();
+        self.append(&mut state, &records)?;
+        Ok(ids)
+    }
+
+    /// entries whose next attempt is due.
+    pub fn due(&self) -> Result<Vec<Entry>, MyError> {
+        let mut state = self.lock();
+        self.sync(&mut state)?;
+        let now = now();
+        let entries = state
+            .pending
+            .values()
+            .filter(|e| e.next_attempt_at <= now)
+            .cloned()
+            .collect();
+        Ok(entries)
+    }
+
+    pub fn delivered(&self, id: u64) -> Result<(), MyError> {
+        let mut state = self.lock();
+        self.append(&mut state, &[Record::Delivered { id }])
+    }
+
+    /// schedules a retry with exponential backoff,
+    /// or moves the entry to the dead letters after `max_attempts`.
+    pub fn failed(&self, entry: &Entry, error: impl Into<String>) -> Result<(), MyError> {
+        let error = error.into();
+        let attempts = entry.attempts + 1;
+        let record = if attempts >= self.config.max_attempts {
+            Record::Dead {
+                id: entry.id,
+                attempts,
+                error,
+            }
+        } else {
+            let delay = self
+                .config
+                .base_delay_secs
+                .saturating_mul(1 << (attempts - 1).min(32))
+                .min(self.config.max_delay_secs);
+            Record::Failed {
+                id: entry.id,
+                attempts,
+                next_attempt_at: now() + delay,
+                error,
+            }
+        };
+        let mut state = self.lock();
+        self.append(&mut state, &[record])
+    }
+
+    pub fn dead_letters(&self) -> Result<Vec<Entry>, MyError> {
+        let mut state = self.lock();
+        self.sync(&mut state)?;
+        Ok(state.dead.values().cloned().collect())
+    }
+
+    /// moves a dead letter back to the queue. returns `false` if there is no such dead letter.
+    pub fn replay(&self, id: u64) -> Result<bool, MyError> {
+        let mut state = self.lock();
+        self.sync(&mut state)?;
+        if !state.dead.contains_key(&id) {
+            return Ok(false);
+        }
+        self.append(&mut state, &[Record::Replayed { id }])?;
+        Ok(true)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// delivers queued messages until the process exits.
+pub async fn run(hook: WebHook) {
+    loop {
+        actix_web::rt::time::sleep(POLL_INTERVAL).await;
+
+        let entries = match hook.outbox().due() {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("failed to read outbox: {:?}", e);
+                continue;
+            }
+        };
+
+        for entry in entries {
+            let result = match hook.deliver(&entry.target, &entry.post).await {
+                Ok(()) => hook.outbox().delivered(entry.id),
+                Err(e) => {
+                    warn!(
+                        "delivery of message {} failed (attempt {}): {:?}",
+                        entry.id,
+                        entry.attempts + 1,
+                        e
+                    );
+                    hook.outbox().failed(&entry, format!("{:?}", e))
+                }
+            };
+            if let Err(e) = result {
+                error!("failed to update outbox: {:?}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TraqTarget;
+
+    fn outbox(name: &str, max_attempts: u32) -> Outbox {
+        let path = std::env::temp_dir().join(format!(
+            "portfolio_webhook-outbox-{}-{}.jsonl",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        Outbox::open(&OutboxConfig {
+            path,
+            max_attempts,
+            base_delay_secs: 0,
+            ..OutboxConfig::default()
+        })
+        .unwrap()
+    }
+
+    fn targets() -> Vec<Target> {
+        vec![
+            Target::Traq(TraqTarget::new("a", "secret")),
+            Target::Traq(TraqTarget::new("b", "secret")),
+        ]
+    }
+
+    #[test]
+    fn enqueues_post_for_every_target() {
+        let outbox = outbox("enqueue", 3);
+        let ids = outbox.enqueue(&targets(), &Post::new("message")).unwrap();
+        assert_eq!(ids, vec![0, 1]);
+
+        let due = outbox.due().unwrap();
+        assert_eq!(due.len(), 2);
+        assert!(due.iter().all(|e| e.post.message == "message"));
+        assert!(outbox
+            .enqueue(&[], &Post::new("message"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn dead_letter_keeps_its_attempts() {
+        let outbox = outbox("dead", 2);
+        outbox
+            .enqueue(&targets()[..1], &Post::new("message"))
+            .unwrap();
+        for _ in 0..2 {
+            let entry = outbox.due().unwrap().remove(0);
+            outbox.failed(&entry, "error").unwrap();
+        }
+        assert!(outbox.due().unwrap().is_empty());
+
+        let reopened = Outbox::open(&outbox.config).unwrap();
+        for outbox in &[outbox, reopened] {
+            let dead = outbox.dead_letters().unwrap();
+            assert_eq!(dead.len(), 1);
+            assert_eq!(dead[0].attempts, 2);
+        }
+    }
+}
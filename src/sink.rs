@@ -0,0 +1,824 @@
+use crate::config::{
+    DiscordTarget, JsonTarget, LimitsConfig, SlackTarget, Target, TraqBotTarget, TraqTarget,
+};
+use crate::embed::Embed;
+use crate::error::MyError;
+use crate::store::{MessageStore, TrackedMessage};
+use crate::text;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use hex::ToHex;
+use log::info;
+use reqwest::header::{HeaderMap, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::{Client, StatusCode};
+use ring::{digest, hmac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+
+const X_TRAQ_SIGNATURE: &str = "X-TRAQ-Signature";
+/// `sha256=<hex>` HMAC of the body, like the one github sends.
+const X_SIGNATURE_256: &str = "X-Signature-256";
+const CLOUDEVENTS_TYPE_PREFIX: &str = "com.github";
+
+const DISCORD_CONTENT_LIMIT: usize = 2000;
+const DISCORD_TITLE_LIMIT: usize = 256;
+const DISCORD_DESCRIPTION_LIMIT: usize = 4096;
+const DISCORD_FIELD_LIMIT: usize = 1024;
+
+/// a rendered message together with its structured forms, as stored in the outbox.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Post {
+    pub message: String,
+    #[serde(default)]
+    pub embed: Option<Embed>,
+    /// github event name, e.g. `issues`.
+    #[serde(default)]
+    pub event: Option<String>,
+    /// values extracted by `utils`, the same ones templates get.
+    #[serde(default)]
+    pub data: Option<Value>,
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub tracking: Option<Tracking>,
+}
+
+/// how a post about an issue or a pull request changes the message posted for it before.
+/// sinks which cannot edit messages post every message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Tracking {
+    /// posts the message, or replaces the content of the posted one.
+    Post { key: String },
+    /// changes the status line of the posted message.
+    Status { key: String, status: String },
+    /// deletes the posted message.
+    Delete { key: String },
+}
+
+impl Tracking {
+    /// e.g. `owner/repo#1`.
+    pub fn key(&self) -> &str {
+        match self {
+            Tracking::Post { key } | Tracking::Status { key, .. } | Tracking::Delete { key } => key,
+        }
+    }
+}
+
+impl Post {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            embed: None,
+            event: None,
+            data: None,
+            created_at: Some(Utc::now()),
+            tracking: None,
+        }
+    }
+
+    pub fn embed(mut self, embed: Option<Embed>) -> Self {
+        self.embed = embed;
+        self
+    }
+
+    pub fn data(mut self, event: &str, data: Value) -> Self {
+        self.event = Some(event.to_owned());
+        self.data = Some(data);
+        self
+    }
+
+    pub fn tracking(mut self, tracking: Option<Tracking>) -> Self {
+        self.tracking = tracking;
+        self
+    }
+}
+
+/// destination of rendered messages.
+#[async_trait(?Send)]
+pub trait Sink {
+    async fn send(&self, post: &Post) -> Result<(), MyError>;
+}
+
+/// sink which posts to `target`.
+pub fn for_target<'a>(
+    client: &'a Client,
+    store: &'a MessageStore,
+    limits: &LimitsConfig,
+    target: &'a Target,
+) -> Box<dyn Sink + 'a> {
+    let message_chars = limits.message_chars;
+    match target {
+        Target::Traq(target) => Box::new(TraqSink {
+            client,
+            target,
+            message_chars,
+        }),
+        Target::TraqBot(target) => Box::new(TraqBotSink {
+            client,
+            target,
+            store,
+            message_chars,
+        }),
+        Target::Slack(target) => Box::new(SlackSink { client, target }),
+        Target::Discord(target) => Box::new(DiscordSink { client, target }),
+        Target::Json(target) => Box::new(JsonSink { client, target }),
+    }
+}
+
+/// a message longer than `message_chars` is posted in several parts.
+/// the outbox retries the whole post, so a part which failed is posted again
+/// together with the parts before it.
+pub struct TraqSink<'a> {
+    client: &'a Client,
+    target: &'a TraqTarget,
+    message_chars: usize,
+}
+
+fn generate_signature<'a>(message: &'a str, secret: &'a str) -> Vec<u8> {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret.as_bytes());
+    let tag = hmac::sign(&key, message.as_bytes());
+    tag.as_ref().to_vec()
+}
+
+#[async_trait(?Send)]
+impl Sink for TraqSink<'_> {
+    async fn send(&self, post: &Post) -> Result<(), MyError> {
+        for part in text::split(&post.message, self.message_chars) {
+            self.post(&part).await?;
+        }
+        Ok(())
+    }
+}
+
+impl TraqSink<'_> {
+    async fn post(&self, message: &str) -> Result<(), MyError> {
+        let url = &self.target.webhook_url();
+
+        let sig = generate_signature(message, &self.target.secret).encode_hex::<String>();
+        let mut headers = HeaderMap::new();
+        headers.insert(X_TRAQ_SIGNATURE, sig.parse().unwrap());
+        headers.insert(CONTENT_TYPE, "text/plain; charset=utf-8".parse().unwrap());
+
+        let res = self
+            .client
+            .post(url)
+            .headers(headers)
+            .body(message.to_owned())
+            .send()
+            .await?;
+        info!(
+            "Message sent to {}, message: {}, response: {:?}",
+            url, message, res
+        );
+        res.error_for_status()?;
+
+        Ok(())
+    }
+}
+
+/// a message longer than `message_chars` is posted in several parts,
+/// and only the first one is edited by later events.
+pub struct TraqBotSink<'a> {
+    client: &'a Client,
+    target: &'a TraqBotTarget,
+    store: &'a MessageStore,
+    message_chars: usize,
+}
+
+impl TraqBotSink<'_> {
+    fn body(&self, content: &str) -> Result<String, MyError> {
+        let body = json!({ "content": content, "embed": self.target.embed });
+        Ok(serde_json::to_string(&body)?)
+    }
+
+    fn bearer(&self) -> String {
+        format!("Bearer {}", self.target.token)
+    }
+
+    /// returns the id of the new message.
+    async fn post(&self, content: &str) -> Result<String, MyError> {
+        let res = self
+            .client
+            .post(self.target.messages_url())
+            .header(AUTHORIZATION, self.bearer())
+            .header(CONTENT_TYPE, "application/json")
+            .body(self.body(content)?)
+            .send()
+            .await?;
+        info!(
+            "Message posted to traq channel {}, message: {}, response: {:?}",
+            self.target.channel_id, content, res
+        );
+        let message: Value = serde_json::from_str(&res.error_for_status()?.text().await?)?;
+        message["id"]
+            .as_str()
+            .map(str::to_owned)
+            .ok_or(MyError::ReadPayloadError)
+    }
+
+    /// returns `false` if the message does not exist anymore.
+    async fn edit(&self, id: &str, content: &str) -> Result<bool, MyError> {
+        let res = self
+            .client
+            .put(self.target.message_url(id))
+            .header(AUTHORIZATION, self.bearer())
+            .header(CONTENT_TYPE, "application/json")
+            .body(self.body(content)?)
+            .send()
+            .await?;
+        info!(
+            "Message {} edited, message: {}, response: {:?}",
+            id, content, res
+        );
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        res.error_for_status()?;
+        Ok(true)
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), MyError> {
+        let res = self
+            .client
+            .delete(self.target.message_url(id))
+            .header(AUTHORIZATION, self.bearer())
+            .send()
+            .await?;
+        info!("Message {} deleted, response: {:?}", id, res);
+        if res.status() != StatusCode::NOT_FOUND {
+            res.error_for_status()?;
+        }
+        Ok(())
+    }
+
+    /// posts `message` and remembers it for `key`.
+    async fn post_tracked(&self, key: &str, message: &TrackedMessage) -> Result<(), MyError> {
+        let id = self.post(&message.render()).await?;
+        let message = TrackedMessage {
+            id,
+            ..message.clone()
+        };
+        self.store.insert(key, message)
+    }
+}
+
+#[async_trait(?Send)]
+impl Sink for TraqBotSink<'_> {
+    async fn send(&self, post: &Post) -> Result<(), MyError> {
+        let mut parts = text::split(&post.message, self.message_chars).into_iter();
+        let first = parts.next().unwrap_or_default();
+        match post.tracking {
+            // only the tracked message changes, the rest was posted with it
+            Some(ref tracking @ Tracking::Status { .. })
+            | Some(ref tracking @ Tracking::Delete { .. }) => {
+                return self.track(tracking, &first).await;
+            }
+            Some(ref tracking) => self.track(tracking, &first).await?,
+            None => {
+                self.post(&first).await?;
+            }
+        }
+        for part in parts {
+            self.post(&part).await?;
+        }
+        Ok(())
+    }
+}
+
+impl TraqBotSink<'_> {
+    /// posts `message`, or edits or deletes the message posted before for the same key.
+    async fn track(&self, tracking: &Tracking, message: &str) -> Result<(), MyError> {
+        let key = format!("{}/{}", self.target.channel_id, tracking.key());
+
+        let tracked = match (tracking, self.store.get(&key)) {
+            (Tracking::Delete { .. }, Some(tracked)) => {
+                self.delete(&tracked.id).await?;
+                return self.store.remove(&key);
+            }
+            (Tracking::Delete { .. }, None) => {
+                self.post(message).await?;
+                return Ok(());
+            }
+            (Tracking::Status { status, .. }, Some(tracked)) => TrackedMessage {
+                status: Some(status.clone()),
+                ..tracked
+            },
+            (Tracking::Post { .. }, Some(tracked)) => TrackedMessage {
+                content: message.to_owned(),
+                ..tracked
+            },
+            // posted before the message was tracked, or by another target
+            (_, None) => {
+                let message = TrackedMessage {
+                    id: String::new(),
+                    content: message.to_owned(),
+                    status: None,
+                };
+                return self.post_tracked(&key, &message).await;
+            }
+        };
+
+        if self.edit(&tracked.id, &tracked.render()).await? {
+            self.store.insert(&key, tracked)
+        } else {
+            // deleted on traq
+            self.post_tracked(&key, &tracked).await
+        }
+    }
+}
+
+pub struct SlackSink<'a> {
+    client: &'a Client,
+    target: &'a SlackTarget,
+}
+
+#[async_trait(?Send)]
+impl Sink for SlackSink<'_> {
+    async fn send(&self, post: &Post) -> Result<(), MyError> {
+        let message = post.message.as_str();
+        let body = json!({ "text": to_mrkdwn(message) });
+
+        let res = self
+            .client
+            .post(&self.target.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(serde_json::to_string(&body)?)
+            .send()
+            .await?;
+        info!(
+            "Message sent to slack, message: {}, response: {:?}",
+            message, res
+        );
+        res.error_for_status()?;
+
+        Ok(())
+    }
+}
+
+/// converts the markdown of a message to slack `mrkdwn`.
+fn to_mrkdwn(message: &str) -> String {
+    message
+        .lines()
+        .map(|line| {
+            let line = line
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;");
+            let line = links_to_mrkdwn(&line).replace("**", "*");
+            let heading = line.trim_start_matches('#');
+            if line.trim() == "---" {
+                String::new()
+            } else if heading.len() < line.len() && heading.starts_with(' ') {
+                format!("*{}*", heading.trim())
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `[text](url)` -> `<url|text>`
+fn links_to_mrkdwn(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find('[') {
+        let link = &rest[start..];
+        let parsed = link.find("](").and_then(|mid| {
+            let end = link[mid..].find(')')? + mid;
+            Some((&link[1..mid], &link[mid + 2..end], end))
+        });
+        match parsed {
+            Some((text, url, end)) => {
+                // images are posted as plain links
+                let before = &rest[..start];
+                out.push_str(before.strip_suffix('!').unwrap_or(before));
+                out.push_str(&format!("<{}|{}>", url, text));
+                rest = &link[end + 1..];
+            }
+            None => break,
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+pub struct DiscordSink<'a> {
+    client: &'a Client,
+    target: &'a DiscordTarget,
+}
+
+#[async_trait(?Send)]
+impl Sink for DiscordSink<'_> {
+    async fn send(&self, post: &Post) -> Result<(), MyError> {
+        match &post.embed {
+            Some(embed) => {
+                let mut embed = embed.clone();
+                embed.title = embed.title.map(|t| truncate(&t, DISCORD_TITLE_LIMIT));
+                embed.description = embed
+                    .description
+                    .map(|d| truncate(&d, DISCORD_DESCRIPTION_LIMIT));
+                for field in &mut embed.fields {
+                    field.value = truncate(&field.value, DISCORD_FIELD_LIMIT);
+                }
+                self.execute(&post.message, &json!({ "embeds": [embed] }))
+                    .await
+            }
+            // a message longer than the limit is posted in several parts
+            None => {
+                for part in text::split(&post.message, DISCORD_CONTENT_LIMIT) {
+                    self.execute(&part, &json!({ "content": part })).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl DiscordSink<'_> {
+    async fn execute(&self, message: &str, body: &Value) -> Result<(), MyError> {
+        let res = self
+            .client
+            .post(&self.target.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(serde_json::to_string(body)?)
+            .send()
+            .await?;
+        info!(
+            "Message sent to discord, message: {}, response: {:?}",
+            message, res
+        );
+        res.error_for_status()?;
+
+        Ok(())
+    }
+}
+
+fn truncate(s: &str, limit: usize) -> String {
+    match s.char_indices().nth(limit - 1) {
+        Some((i, _)) => format!("{}…", &s[..i]),
+        None => s.to_owned(),
+    }
+}
+
+pub struct JsonSink<'a> {
+    client: &'a Client,
+    target: &'a JsonTarget,
+}
+
+impl JsonSink<'_> {
+    /// normalized form of the post, independent of the github payload.
+    fn document(post: &Post) -> Value {
+        let data = post.data.as_ref().unwrap_or(&Value::Null);
+        json!({
+            "event": post.event,
+            "action": data["action"]["key"],
+            "repository": data["full_name"],
+            "actor": data["action"]["sender"],
+            "message": post.message,
+            "data": data,
+        })
+    }
+
+    /// CloudEvents 1.0 envelope in structured mode.
+    fn cloud_event(post: &Post, document: Value) -> Value {
+        let created_at = post.created_at.unwrap_or_else(Utc::now);
+        let event = post.event.as_deref().unwrap_or("unknown");
+        let kind = match document["action"].as_str() {
+            Some(action) => format!("{}.{}.{}", CLOUDEVENTS_TYPE_PREFIX, event, action),
+            None => format!("{}.{}", CLOUDEVENTS_TYPE_PREFIX, event),
+        };
+        let source = match document["repository"].as_str() {
+            Some(repo) => format!("https://github.com/{}", repo),
+            None => "https://github.com".to_owned(),
+        };
+        // stays the same when the delivery is retried
+        let seed = format!("{}\n{}", created_at.to_rfc3339(), post.message);
+        let id = digest::digest(&digest::SHA256, seed.as_bytes())
+            .as_ref()
+            .encode_hex::<String>();
+
+        json!({
+            "specversion": "1.0",
+            "id": id,
+            "source": source,
+            "type": kind,
+            "time": created_at.to_rfc3339(),
+            "datacontenttype": "application/json",
+            "data": document,
+        })
+    }
+}
+
+#[async_trait(?Send)]
+impl Sink for JsonSink<'_> {
+    async fn send(&self, post: &Post) -> Result<(), MyError> {
+        let document = Self::document(post);
+        let (body, content_type) = if self.target.cloudevents {
+            (
+                Self::cloud_event(post, document),
+                "application/cloudevents+json",
+            )
+        } else {
+            (document, "application/json")
+        };
+        let body = serde_json::to_string(&body)?;
+
+        let mut request = self
+            .client
+            .post(&self.target.url)
+            .header(CONTENT_TYPE, content_type);
+        if let Some(secret) = &self.target.secret {
+            let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+            let sig = hmac::sign(&key, body.as_bytes())
+                .as_ref()
+                .encode_hex::<String>();
+            request = request.header(X_SIGNATURE_256, format!("sha256={}", sig));
+        }
+
+        let res = request.body(body).send().await?;
+        info!(
+            "Message sent to {}, message: {}, response: {:?}",
+            self.target.url, post.message, res
+        );
+        res.error_for_status()?;
+
+        Ok(())
+    }
+}
+
+/// keeps every post in memory instead of sending it.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySink {
+    posts: Arc<Mutex<Vec<Post>>>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn posts(&self) -> Vec<Post> {
+        self.posts.lock().expect("poisoned lock").clone()
+    }
+
+    pub fn messages(&self) -> Vec<String> {
+        self.posts().into_iter().map(|p| p.message).collect()
+    }
+}
+
+#[async_trait(?Send)]
+impl Sink for MemorySink {
+    async fn send(&self, post: &Post) -> Result<(), MyError> {
+        self.posts.lock().expect("poisoned lock").push(post.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StoreConfig;
+    use actix_web::{http::header::AUTHORIZATION, web, App, HttpRequest, HttpResponse, HttpServer};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// contents of the messages on the mock traQ server by id.
+    type Messages = Arc<Mutex<HashMap<String, String>>>;
+
+    const CHANNEL: &str = "channel";
+    const KEY: &str = "o/repo#1";
+
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+    fn authorized(req: &HttpRequest) -> bool {
+        req.headers()
+            .get(AUTHORIZATION)
+            .map(|v| v == "Bearer token")
+            .unwrap_or(false)
+    }
+
+    fn content(body: &str) -> String {
+        let body: Value = serde_json::from_str(body).expect("json body");
+        body["content"].as_str().expect("content").to_owned()
+    }
+
+    async fn post_message(
+        req: HttpRequest,
+        messages: web::Data<Messages>,
+        body: String,
+    ) -> HttpResponse {
+        if !authorized(&req) {
+            return HttpResponse::Unauthorized().finish();
+        }
+        let id = format!("message-{}", NEXT_ID.fetch_add(1, Ordering::SeqCst));
+        messages.lock().unwrap().insert(id.clone(), content(&body));
+        HttpResponse::Created().json(json!({ "id": id }))
+    }
+
+    async fn edit_message(
+        req: HttpRequest,
+        messages: web::Data<Messages>,
+        id: web::Path<String>,
+        body: String,
+    ) -> HttpResponse {
+        if !authorized(&req) {
+            return HttpResponse::Unauthorized().finish();
+        }
+        match messages.lock().unwrap().get_mut(id.as_str()) {
+            Some(message) => {
+                *message = content(&body);
+                HttpResponse::NoContent().finish()
+            }
+            None => HttpResponse::NotFound().finish(),
+        }
+    }
+
+    async fn delete_message(
+        req: HttpRequest,
+        messages: web::Data<Messages>,
+        id: web::Path<String>,
+    ) -> HttpResponse {
+        if !authorized(&req) {
+            return HttpResponse::Unauthorized().finish();
+        }
+        match messages.lock().unwrap().remove(id.as_str()) {
+            Some(_) => HttpResponse::NoContent().finish(),
+            None => HttpResponse::NotFound().finish(),
+        }
+    }
+
+    /// starts a server with the message endpoints of the traQ bot API
+    /// and returns its base url.
+    fn mock_traq(messages: &Messages) -> String {
+        let messages = Arc::clone(messages);
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(Arc::clone(&messages)))
+                .route("/channels/{channel}/messages", web::post().to(post_message))
+                .route("/messages/{id}", web::put().to(edit_message))
+                .route("/messages/{id}", web::delete().to(delete_message))
+        })
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .expect("failed to bind the mock server");
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+        format!("http://{}", addr)
+    }
+
+    fn target(base_url: String) -> TraqBotTarget {
+        TraqBotTarget {
+            channel_id: CHANNEL.to_owned(),
+            token: "token".to_owned(),
+            base_url,
+            embed: true,
+        }
+    }
+
+    fn store(name: &str) -> MessageStore {
+        let path = std::env::temp_dir().join(format!(
+            "portfolio_webhook-{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        MessageStore::open(&StoreConfig { path }).unwrap()
+    }
+
+    fn tracked(message: &str, tracking: Tracking) -> Post {
+        Post::new(message).tracking(Some(tracking))
+    }
+
+    fn status(status: &str) -> Tracking {
+        Tracking::Status {
+            key: KEY.to_owned(),
+            status: status.to_owned(),
+        }
+    }
+
+    fn tracked_id(store: &MessageStore) -> Option<String> {
+        store
+            .get(&format!("{}/{}", CHANNEL, KEY))
+            .map(|message| message.id)
+    }
+
+    #[actix_rt::test]
+    async fn edits_and_deletes_tracked_message() {
+        let messages = Messages::default();
+        let target = target(mock_traq(&messages));
+        let store = store("tracked");
+        let client = Client::new();
+        let sink = TraqBotSink {
+            client: &client,
+            target: &target,
+            store: &store,
+            message_chars: 1000,
+        };
+
+        let opened = Tracking::Post {
+            key: KEY.to_owned(),
+        };
+        sink.send(&tracked("opened", opened)).await.unwrap();
+        let id = tracked_id(&store).expect("the message is tracked");
+        assert_eq!(messages.lock().unwrap()[&id], "opened");
+
+        sink.send(&tracked("merged", status("Merged")))
+            .await
+            .unwrap();
+        assert_eq!(messages.lock().unwrap().len(), 1);
+        assert_eq!(messages.lock().unwrap()[&id], "opened\n**Status:** Merged");
+
+        let deleted = Tracking::Delete {
+            key: KEY.to_owned(),
+        };
+        sink.send(&tracked("deleted", deleted)).await.unwrap();
+        assert!(messages.lock().unwrap().is_empty());
+        assert_eq!(tracked_id(&store), None);
+    }
+
+    #[actix_rt::test]
+    async fn reposts_message_deleted_on_traq() {
+        let messages = Messages::default();
+        let target = target(mock_traq(&messages));
+        let store = store("reposted");
+        let client = Client::new();
+        let sink = TraqBotSink {
+            client: &client,
+            target: &target,
+            store: &store,
+            message_chars: 1000,
+        };
+
+        let opened = Tracking::Post {
+            key: KEY.to_owned(),
+        };
+        sink.send(&tracked("opened", opened)).await.unwrap();
+        let id = tracked_id(&store).expect("the message is tracked");
+        messages.lock().unwrap().clear();
+
+        sink.send(&tracked("closed", status("Closed")))
+            .await
+            .unwrap();
+        let reposted = tracked_id(&store).expect("the message is still tracked");
+        assert_ne!(reposted, id);
+        assert_eq!(
+            messages.lock().unwrap()[&reposted],
+            "opened\n**Status:** Closed"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn posts_untracked_message_in_parts() {
+        let messages = Messages::default();
+        let target = target(mock_traq(&messages));
+        let store = store("parts");
+        let client = Client::new();
+        let sink = TraqBotSink {
+            client: &client,
+            target: &target,
+            store: &store,
+            message_chars: 20,
+        };
+
+        sink.send(&Post::new("first line\nsecond line\n"))
+            .await
+            .unwrap();
+        assert_eq!(messages.lock().unwrap().len(), 2);
+        assert_eq!(tracked_id(&store), None);
+    }
+
+    #[actix_rt::test]
+    async fn changes_status_without_reposting_parts() {
+        let messages = Messages::default();
+        let target = target(mock_traq(&messages));
+        let store = store("status-parts");
+        let client = Client::new();
+        let sink = TraqBotSink {
+            client: &client,
+            target: &target,
+            store: &store,
+            message_chars: 20,
+        };
+
+        let opened = Tracking::Post {
+            key: KEY.to_owned(),
+        };
+        let message = "first line\nsecond line\n";
+        sink.send(&tracked(message, opened)).await.unwrap();
+        assert_eq!(messages.lock().unwrap().len(), 2);
+
+        sink.send(&tracked(message, status("Closed")))
+            .await
+            .unwrap();
+        assert_eq!(messages.lock().unwrap().len(), 2);
+
+        let deleted = Tracking::Delete {
+            key: KEY.to_owned(),
+        };
+        sink.send(&tracked(message, deleted)).await.unwrap();
+        assert_eq!(messages.lock().unwrap().len(), 1);
+    }
+}
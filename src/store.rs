@@ -0,0 +1,125 @@
+use crate::config::StoreConfig;
+use crate::error::MyError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+/// traQ message posted for an issue or a pull request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackedMessage {
+    pub id: String,
+    /// the message without the status line.
+    pub content: String,
+    pub status: Option<String>,
+}
+
+impl TrackedMessage {
+    /// the content with the status line at the end.
+    pub fn render(&self) -> String {
+        match self.status {
+            Some(ref status) => format!("{}\n**Status:** {}", self.content.trim_end(), status),
+            None => self.content.clone(),
+        }
+    }
+}
+
+/// ids of the traQ messages posted by the bot, so that later events edit them.
+///
+/// the whole map is written to the file on every change, which is fine for the
+/// number of open issues and pull requests.
+pub struct MessageStore {
+    path: PathBuf,
+    messages: Mutex<HashMap<String, TrackedMessage>>,
+}
+
+impl MessageStore {
+    pub fn open(config: &StoreConfig) -> Result<Self, MyError> {
+        let messages = if config.path.exists() {
+            serde_json::from_str(&fs::read_to_string(&config.path)?)?
+        } else {
+            HashMap::new()
+        };
+        Ok(Self {
+            path: config.path.clone(),
+            messages: Mutex::new(messages),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, TrackedMessage>> {
+        self.messages.lock().expect("poisoned lock")
+    }
+
+    pub fn get(&self, key: &str) -> Option<TrackedMessage> {
+        self.lock().get(key).cloned()
+    }
+
+    pub fn insert(&self, key: &str, message: TrackedMessage) -> Result<(), MyError> {
+        let mut messages = self.lock();
+        messages.insert(key.to_owned(), message);
+        self.save(&messages)
+    }
+
+    pub fn remove(&self, key: &str) -> Result<(), MyError> {
+        let mut messages = self.lock();
+        if messages.remove(key).is_some() {
+            self.save(&messages)?;
+        }
+        Ok(())
+    }
+
+    fn save(&self, messages: &HashMap<String, TrackedMessage>) -> Result<(), MyError> {
+        let tmp = self.path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(serde_json::to_string(messages)?.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(content: &str, status: Option<&str>) -> TrackedMessage {
+        TrackedMessage {
+            id: "id".to_owned(),
+            content: content.to_owned(),
+            status: status.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn renders_content_without_status() {
+        assert_eq!(message("opened\n", None).render(), "opened\n");
+    }
+
+    #[test]
+    fn renders_status_line_at_the_end() {
+        let message = message("opened\n\n", Some("Merged by `alice`"));
+        assert_eq!(message.render(), "opened\n**Status:** Merged by `alice`");
+    }
+
+    #[test]
+    fn keeps_messages_after_reopening() {
+        let path = std::env::temp_dir().join(format!(
+            "portfolio_webhook-store-{}.json",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        let config = StoreConfig { path };
+
+        let store = MessageStore::open(&config).unwrap();
+        store.insert("a", message("a", None)).unwrap();
+        store.insert("b", message("b", Some("Closed"))).unwrap();
+        store.remove("a").unwrap();
+
+        let store = MessageStore::open(&config).unwrap();
+        assert!(store.get("a").is_none());
+        assert_eq!(store.get("b").unwrap().render(), "b\n**Status:** Closed");
+        fs::remove_file(&config.path).unwrap();
+    }
+}
@@ -0,0 +1,269 @@
+use crate::config::{Config, LimitsConfig};
+use crate::error::MyError;
+use crate::users::UserMap;
+use crate::utils::{hidden, prelude::*};
+use handlebars::{Context, Handlebars, Helper, HelperResult, Output, RenderContext, RenderError};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// user defined templates from the config.
+///
+/// a template is looked up by `<event>.<action>` first and then by `<event>`,
+/// preferring templates of the route over the global ones.
+///
+/// `{{mention login}}` renders `@name` of the traQ user which `login` is mapped to.
+pub struct Templates {
+    registry: Handlebars<'static>,
+}
+
+impl Templates {
+    pub fn new(config: &Config, users: Arc<UserMap>) -> Result<Self, MyError> {
+        let mut registry = Handlebars::new();
+        // messages are markdown, not html
+        registry.register_escape_fn(handlebars::no_escape);
+        registry.register_helper(
+            "mention",
+            Box::new(
+                move |h: &Helper,
+                      _: &Handlebars,
+                      _: &Context,
+                      _: &mut RenderContext,
+                      out: &mut dyn Output|
+                      -> HelperResult {
+                    let login = h
+                        .param(0)
+                        .and_then(|v| v.value().as_str())
+                        .ok_or_else(|| RenderError::new("mention: login is required"))?;
+                    out.write(&users.mention(login))?;
+                    Ok(())
+                },
+            ),
+        );
+
+        let mut register = |name: String, template: &str| {
+            registry
+                .register_template_string(&name, template)
+                .map_err(|e| MyError::ConfigError(format!("template {}: {}", name, e)))
+        };
+        for (key, template) in &config.templates {
+            register(key.clone(), template)?;
+        }
+        for (i, route) in config.routes.iter().enumerate() {
+            for (key, template) in &route.templates {
+                register(route_key(i, key), template)?;
+            }
+        }
+
+        Ok(Self { registry })
+    }
+
+    /// returns `None` if there is no template for the event.
+    pub fn render(
+        &self,
+        route: Option<usize>,
+        event: &str,
+        action: Option<&str>,
+        data: &Value,
+    ) -> Result<Option<String>, MyError> {
+        let mut keys = Vec::with_capacity(2);
+        if let Some(action) = action {
+            keys.push(format!("{}.{}", event, action));
+        }
+        keys.push(event.to_owned());
+
+        let mut names = Vec::with_capacity(4);
+        if let Some(i) = route {
+            names.extend(keys.iter().map(|key| route_key(i, key)));
+        }
+        names.extend(keys);
+
+        match names.iter().find(|name| self.registry.has_template(name)) {
+            Some(name) => Ok(Some(self.registry.render(name, data)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+fn route_key(route: usize, key: &str) -> String {
+    format!("routes.{}.{}", route, key)
+}
+
+/// collects the values extracted by the `utils` traits as template variables.
+///
+/// bodies are sanitized and cut to the limits like the built-in messages.
+pub struct TemplateContext<T> {
+    event: Rc<T>,
+    vars: Map<String, Value>,
+    users: Arc<UserMap>,
+}
+
+impl<T> TemplateContext<T>
+where
+    T: hidden::Marker,
+{
+    pub fn new(event: Rc<T>) -> Self {
+        Self {
+            event,
+            vars: Map::new(),
+            users: Arc::default(),
+        }
+    }
+
+    /// users who are mentioned by their traQ names in the bodies.
+    pub fn users(mut self, users: &Arc<UserMap>) -> Self {
+        self.users = Arc::clone(users);
+        self
+    }
+
+    pub fn var(mut self, name: &str, value: impl Serialize) -> Self {
+        let value = serde_json::to_value(value).unwrap_or(Value::Null);
+        self.vars.insert(name.to_owned(), value);
+        self
+    }
+
+    pub fn build(self) -> Value {
+        Value::Object(self.vars)
+    }
+}
+
+impl<T> TemplateContext<T>
+where
+    T: TIssue,
+{
+    pub fn issue(self) -> Self {
+        let issue = self.event.issue();
+        self.var("issue", issue)
+    }
+}
+
+impl<T> TemplateContext<T>
+where
+    T: TPullRequest,
+{
+    pub fn pr(self) -> Self {
+        let pr = self.event.pr();
+        self.var("pr", pr)
+    }
+}
+
+impl<T> TemplateContext<T>
+where
+    T: TRepository,
+{
+    pub fn repo(self) -> Self {
+        let repo = self.event.repo();
+        let full_name = repo.as_ref().map(|r| r.full_name());
+        self.var("repo", repo).var("full_name", full_name)
+    }
+}
+
+impl<T> TemplateContext<T>
+where
+    T: TAction,
+{
+    pub fn action(self) -> Self {
+        let action = self.event.action();
+        self.var("action", action)
+    }
+}
+
+impl<T> TemplateContext<T>
+where
+    T: TCommit,
+{
+    pub fn commits(self) -> Self {
+        let commits = self.event.commits();
+        self.var("commits", commits)
+    }
+}
+
+impl<T> TemplateContext<T>
+where
+    T: TLabel,
+{
+    pub fn labels(self) -> Self {
+        let labels = self.event.labels();
+        self.var("labels", labels)
+    }
+}
+
+impl<T> TemplateContext<T>
+where
+    T: TAssignee,
+{
+    pub fn assignees(self) -> Self {
+        let assignees = self.event.assignees();
+        self.var("assignees", assignees)
+    }
+}
+
+impl<T> TemplateContext<T>
+where
+    T: TComment,
+{
+    pub fn comment(self, limits: &LimitsConfig) -> Self {
+        let comment = self
+            .event
+            .comment()
+            .map(|c| c.sanitized(&self.users, limits));
+        self.var("comment", comment)
+    }
+}
+
+impl<T> TemplateContext<T>
+where
+    T: TReview,
+{
+    pub fn review(self, limits: &LimitsConfig) -> Self {
+        let review = self
+            .event
+            .review()
+            .map(|r| r.sanitized(&self.users, limits));
+        self.var("review", review)
+    }
+}
+
+impl<T> TemplateContext<T>
+where
+    T: TRelease,
+{
+    pub fn release(self, limits: &LimitsConfig) -> Self {
+        let release = self
+            .event
+            .release()
+            .map(|r| r.sanitized(&self.users, limits));
+        self.var("release", release)
+    }
+}
+
+impl<T> TemplateContext<T>
+where
+    T: TGitRef,
+{
+    pub fn git_ref(self) -> Self {
+        let git_ref = self.event.git_ref();
+        self.var("ref", git_ref)
+    }
+}
+
+impl<T> TemplateContext<T>
+where
+    T: TWorkflow,
+{
+    pub fn workflow(self) -> Self {
+        let workflow = self.event.workflow();
+        self.var("workflow", workflow)
+    }
+}
+
+impl<T> TemplateContext<T>
+where
+    T: TDiscussion,
+{
+    pub fn discussion(self) -> Self {
+        let discussion = self.event.discussion();
+        self.var("discussion", discussion)
+    }
+}
@@ -0,0 +1,526 @@
+use crate::users::UserMap;
+
+const FENCE: &str = "```";
+
+/// cut sections end with this, linked to the full text on github.
+pub const READ_MORE: &str = "…(read more)";
+
+pub fn read_more(url: &str) -> String {
+    format!("[{}]({})", READ_MORE, url)
+}
+
+/// cuts `s` to `chars` characters and `lines` lines.
+/// returns `None` if `s` already fits.
+pub fn truncate(s: &str, chars: usize, lines: usize) -> Option<String> {
+    let mut end = s.len();
+    if let Some((i, _)) = s.match_indices('\n').nth(lines.saturating_sub(1)) {
+        end = i;
+    }
+    match s.char_indices().nth(chars) {
+        Some((i, _)) if i < end => end = soft_boundary(s, i),
+        _ if end == s.len() => return None,
+        _ => {}
+    }
+
+    let mut cut = s[..end].trim_end().to_owned();
+    close_markdown(&mut cut);
+    Some(cut)
+}
+
+/// `truncate` with a `…(read more)` link to `url` at the end.
+pub fn truncate_with_link(s: &str, chars: usize, lines: usize, url: &str) -> String {
+    match truncate(s, chars, lines) {
+        Some(cut) => format!("{}\n{}", cut, read_more(url)),
+        None => s.to_owned(),
+    }
+}
+
+/// moves `end` back to a line break or a space, so that words and links are not cut,
+/// unless that drops more than a fifth of the text.
+fn soft_boundary(s: &str, end: usize) -> usize {
+    let head = &s[..end];
+    let min = end - end / 5;
+    head.rfind('\n')
+        .filter(|&i| i >= min)
+        .or_else(|| head.rfind(' ').filter(|&i| i >= min))
+        .unwrap_or(end)
+}
+
+fn in_fence(s: &str) -> bool {
+    s.lines()
+        .filter(|l| l.trim_start().starts_with(FENCE))
+        .count()
+        % 2
+        == 1
+}
+
+/// closes a code block or inline code left open by a cut.
+fn close_markdown(s: &mut String) {
+    if in_fence(s) {
+        s.push('\n');
+        s.push_str(FENCE);
+    } else if s.lines().last().map(|l| l.matches('`').count() % 2 == 1) == Some(true) {
+        s.push('`');
+    }
+}
+
+/// splits `message` into parts of at most `chars` characters.
+/// parts are split between lines, and a code block which is split is closed and reopened.
+pub fn split(message: &str, chars: usize) -> Vec<String> {
+    if message.chars().count() <= chars {
+        return vec![message.to_owned()];
+    }
+    // room for closing and reopening a code block
+    let limit = chars.saturating_sub(FENCE.len() + 1).max(1);
+
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut len = 0;
+    let mut fence: Option<String> = None;
+    for whole in message.lines() {
+        // a line in a code block may follow the reopened fence
+        let reopen = fence.as_ref().map_or(0, |open| open.chars().count() + 1);
+        let width = limit.saturating_sub(reopen + 1).max(1);
+        for line in hard_split(whole, width) {
+            let line_len = line.chars().count() + 1;
+            if len + line_len > limit && !part.is_empty() {
+                if fence.is_some() {
+                    part.push_str(FENCE);
+                    part.push('\n');
+                }
+                parts.push(std::mem::take(&mut part));
+                len = 0;
+                if let Some(open) = &fence {
+                    part.push_str(open);
+                    part.push('\n');
+                    len = open.chars().count() + 1;
+                }
+            }
+            if line.trim_start().starts_with(FENCE) {
+                fence = match fence {
+                    Some(_) => None,
+                    None => Some(line.trim_start().to_owned()),
+                };
+            }
+            part.push_str(line);
+            part.push('\n');
+            len += line_len;
+        }
+    }
+    if !part.is_empty() {
+        parts.push(part);
+    }
+    parts
+}
+
+/// splits a line which alone is longer than `chars` on character boundaries.
+fn hard_split(line: &str, chars: usize) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut rest = line;
+    while let Some((i, _)) = rest.char_indices().nth(chars) {
+        parts.push(&rest[..i]);
+        rest = &rest[i..];
+    }
+    parts.push(rest);
+    parts
+}
+
+/// makes markdown from github safe to post.
+///
+/// mentions of users in `users` are changed to their traQ names and the other
+/// mentions are put in inline code, html comments are removed, other html tags are
+/// replaced with markdown or dropped, and an open code block is closed.
+/// code is left as it is.
+pub fn sanitize(s: &str, users: &UserMap) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut fence = false;
+    let mut in_comment = false;
+    for line in s.lines() {
+        if !in_comment && line.trim_start().starts_with(FENCE) {
+            fence = !fence;
+        } else if !fence {
+            let stripped = strip_comments(line, &mut in_comment);
+            // lines which only held a comment
+            if stripped.trim().is_empty() && !line.trim().is_empty() {
+                continue;
+            }
+            out.push_str(&sanitize_inline(&stripped, users));
+            out.push('\n');
+            continue;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    if fence {
+        out.push_str(FENCE);
+        out.push('\n');
+    }
+    out.truncate(out.trim_end().len());
+    out
+}
+
+fn strip_comments(line: &str, in_comment: &mut bool) -> String {
+    let mut out = String::new();
+    let mut rest = line;
+    loop {
+        if *in_comment {
+            match rest.find("-->") {
+                Some(i) => {
+                    rest = &rest[i + 3..];
+                    *in_comment = false;
+                }
+                None => return out,
+            }
+        } else {
+            match rest.find("<!--") {
+                Some(i) => {
+                    out.push_str(&rest[..i]);
+                    rest = &rest[i + 4..];
+                    *in_comment = true;
+                }
+                None => {
+                    out.push_str(rest);
+                    return out;
+                }
+            }
+        }
+    }
+}
+
+/// puts the mentions in a title or a commit subject in inline code,
+/// so that it does not notify anyone even if the user is in `users`.
+pub fn neutralize(s: &str) -> String {
+    let users = UserMap::default();
+    s.split('\n')
+        .map(|line| outside_code(line, |part| neutralize_mentions(part, &users)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// sanitizes the parts of a line which are not inline code.
+fn sanitize_inline(line: &str, users: &UserMap) -> String {
+    outside_code(line, |part| neutralize_mentions(&strip_html(part), users))
+}
+
+/// applies `f` to the parts of `line` which are not inline code.
+///
+/// a backtick without a closing one does not start code. it is escaped, so that it
+/// does not pair with the backticks `f` adds.
+fn outside_code(line: &str, f: impl Fn(&str) -> String) -> String {
+    let parts: Vec<&str> = line.split('`').collect();
+    let unclosed = parts.len() % 2 == 0;
+    let mut out = String::with_capacity(line.len());
+    for (i, part) in parts.iter().enumerate() {
+        if i % 2 == 0 {
+            out.push_str(&f(part));
+            continue;
+        }
+        if unclosed && i == parts.len() - 1 {
+            out.push_str("\\`");
+            out.push_str(&f(part));
+        } else {
+            out.push('`');
+            out.push_str(part);
+            out.push('`');
+        }
+    }
+    out
+}
+
+/// `@someone` -> `` `@someone` ``, so that it does not notify anyone,
+/// or `@traq_name` if `someone` is in `users`.
+fn neutralize_mentions(s: &str, users: &UserMap) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.char_indices().peekable();
+    let mut prev: Option<char> = None;
+    while let Some((i, c)) = chars.next() {
+        let starts_mention = c == '@'
+            && !prev.map(|p| p.is_alphanumeric()).unwrap_or(false)
+            && chars
+                .peek()
+                .map(|&(_, n)| n.is_alphanumeric())
+                .unwrap_or(false);
+        if !starts_mention {
+            out.push(c);
+            prev = Some(c);
+            continue;
+        }
+
+        let mut end = s.len();
+        while let Some(&(j, n)) = chars.peek() {
+            if n.is_alphanumeric() || n == '-' || n == '_' || n == '/' {
+                chars.next();
+            } else {
+                end = j;
+                break;
+            }
+        }
+        match users.traq(&s[i + 1..end]) {
+            Some(name) => {
+                out.push('@');
+                out.push_str(name);
+            }
+            None => {
+                out.push('`');
+                out.push_str(&s[i..end]);
+                out.push('`');
+            }
+        }
+        prev = s[..end].chars().last();
+    }
+    out
+}
+
+/// html elements which are dropped. other tags, e.g. `Vec<String>`, are prose.
+const HTML_TAGS: &[&str] = &[
+    "a",
+    "abbr",
+    "b",
+    "blockquote",
+    "br",
+    "code",
+    "dd",
+    "del",
+    "details",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "img",
+    "ins",
+    "kbd",
+    "li",
+    "ol",
+    "p",
+    "picture",
+    "pre",
+    "q",
+    "s",
+    "samp",
+    "source",
+    "span",
+    "strike",
+    "strong",
+    "sub",
+    "summary",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "tr",
+    "tt",
+    "u",
+    "ul",
+    "var",
+    "video",
+];
+
+/// replaces html tags with markdown where there is an equivalent and drops the others.
+fn strip_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match parse_tag(rest) {
+            Some((tag, len)) => {
+                out.push_str(&tag.markdown());
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('<');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+struct Tag<'a> {
+    name: String,
+    closing: bool,
+    attrs: &'a str,
+}
+
+impl Tag<'_> {
+    fn attr(&self, name: &str) -> Option<&str> {
+        let key = format!("{}=\"", name);
+        let start = self.attrs.find(&key)? + key.len();
+        let len = self.attrs[start..].find('"')?;
+        Some(&self.attrs[start..start + len])
+    }
+
+    fn markdown(&self) -> String {
+        match self.name.as_str() {
+            "br" => "\n".to_owned(),
+            "b" | "strong" | "summary" => "**".to_owned(),
+            "img" if !self.closing => match self.attr("src") {
+                Some(src) => format!("[{}]({})", self.attr("alt").unwrap_or("image"), src),
+                None => String::new(),
+            },
+            _ => String::new(),
+        }
+    }
+}
+
+/// parses the tag at the start of `s` and returns it with its length.
+fn parse_tag(s: &str) -> Option<(Tag<'_>, usize)> {
+    let end = s.find('>')?;
+    let inner = &s[1..end];
+    if inner.contains('<') {
+        return None;
+    }
+    let closing = inner.starts_with('/');
+    let inner = inner.trim_start_matches('/');
+    let name_len = inner
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+        .unwrap_or(inner.len());
+    let name = &inner[..name_len];
+    let attrs = &inner[name_len..];
+    let name = name.to_ascii_lowercase();
+    let valid = HTML_TAGS.contains(&name.as_str())
+        && (attrs.is_empty() || attrs.starts_with(|c: char| c.is_whitespace() || c == '/'));
+    if !valid {
+        return None;
+    }
+
+    let tag = Tag {
+        name,
+        closing,
+        attrs,
+    };
+    Some((tag, end + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn users() -> UserMap {
+        UserMap::from_toml("[github]\nalice = \"alice_traq\"").unwrap()
+    }
+
+    fn fences(s: &str) -> usize {
+        s.lines()
+            .filter(|l| l.trim_start().starts_with(FENCE))
+            .count()
+    }
+
+    #[test]
+    fn truncate_keeps_text_which_fits() {
+        assert_eq!(truncate("short\ntext", 100, 10), None);
+    }
+
+    #[test]
+    fn truncate_cuts_multibyte_text_between_chars() {
+        assert_eq!(truncate("あいうえお", 3, 10).as_deref(), Some("あいう"));
+        assert_eq!(truncate("🦀🦀🦀 🦀🦀", 4, 10).as_deref(), Some("🦀🦀🦀"));
+    }
+
+    #[test]
+    fn truncate_closes_code_block() {
+        let cut = truncate("```rust\nlet a = 1;\nlet b = 2;\n```", 100, 2).unwrap();
+        assert_eq!(cut, "```rust\nlet a = 1;\n```");
+    }
+
+    #[test]
+    fn split_keeps_message_which_fits() {
+        assert_eq!(split("message", 10), vec!["message"]);
+    }
+
+    #[test]
+    fn split_cuts_multibyte_lines_between_chars() {
+        let message = "あ".repeat(25);
+        let parts = split(&message, 10);
+        assert!(parts.iter().all(|p| p.chars().count() <= 10));
+        assert_eq!(parts.concat().replace('\n', ""), message);
+    }
+
+    #[test]
+    fn split_closes_and_reopens_code_block() {
+        let code: String = (0..10).map(|i| format!("let a{} = {};\n", i, i)).collect();
+        let message = format!("text\n```rust\n{}```\n", code);
+        let parts = split(&message, 50);
+        assert!(parts.len() > 1);
+        for part in &parts {
+            assert!(part.chars().count() <= 50, "too long: {:?}", part);
+            assert_eq!(fences(part) % 2, 0, "unbalanced: {:?}", part);
+        }
+        assert!(parts[1..].iter().all(|p| p.starts_with("```rust\n")));
+    }
+
+    #[test]
+    fn split_leaves_room_for_reopened_code_block() {
+        let message = format!("```rust\n{}\n```\n", "x".repeat(200));
+        let parts = split(&message, 50);
+        assert!(parts.len() > 1);
+        for part in &parts {
+            assert!(part.chars().count() <= 50, "too long: {:?}", part);
+            assert_eq!(fences(part) % 2, 0, "unbalanced: {:?}", part);
+        }
+        assert_eq!(
+            parts
+                .concat()
+                .replace("```rust", "")
+                .replace("```", "")
+                .replace('\n', ""),
+            "x".repeat(200)
+        );
+    }
+
+    #[test]
+    fn sanitize_maps_known_users_and_neutralizes_others() {
+        assert_eq!(
+            sanitize("thanks @alice and @bob", &users()),
+            "thanks @alice_traq and `@bob`"
+        );
+        assert_eq!(
+            sanitize("mail a@example.com", &users()),
+            "mail a@example.com"
+        );
+    }
+
+    #[test]
+    fn sanitize_leaves_code_as_it_is() {
+        let s = "`@bob` and\n```\n@bob <b>\n```";
+        assert_eq!(sanitize(s, &users()), s);
+    }
+
+    #[test]
+    fn sanitize_neutralizes_mention_after_unbalanced_backtick() {
+        assert_eq!(sanitize("it's `foo @bar", &users()), "it's \\`foo `@bar`");
+    }
+
+    #[test]
+    fn sanitize_replaces_html_and_keeps_generics() {
+        assert_eq!(
+            sanitize("<b>returns</b> Vec<String><!-- note -->", &users()),
+            "**returns** Vec<String>"
+        );
+        assert_eq!(
+            sanitize("<details><summary>Log</summary>", &users()),
+            "**Log**"
+        );
+    }
+
+    #[test]
+    fn neutralize_never_notifies() {
+        assert_eq!(
+            neutralize("Bump @types/node for @alice"),
+            "Bump `@types/node` for `@alice`"
+        );
+        assert_eq!(neutralize("Fix `@keep`"), "Fix `@keep`");
+    }
+}
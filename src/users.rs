@@ -0,0 +1,68 @@
+use crate::error::MyError;
+use serde::Deserialize;
+use std::{collections::HashMap, fs, path::Path};
+
+/// github users and the traQ users they are.
+///
+/// ```toml
+/// [github]
+/// octocat = "traq_name"
+///
+/// [email]
+/// "octocat@example.com" = "traq_name"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UserMap {
+    /// github login -> traQ user name.
+    #[serde(default)]
+    github: HashMap<String, String>,
+    /// commit author email -> traQ user name, for commits without a github login.
+    #[serde(default)]
+    email: HashMap<String, String>,
+}
+
+impl UserMap {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, MyError> {
+        let s = fs::read_to_string(path)?;
+        Self::from_toml(&s)
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self, MyError> {
+        let users: Self = toml::from_str(s)?;
+        // logins and emails are case insensitive
+        let lowercase = |map: HashMap<String, String>| {
+            map.into_iter()
+                .map(|(k, v)| (k.to_lowercase(), v))
+                .collect()
+        };
+        Ok(Self {
+            github: lowercase(users.github),
+            email: lowercase(users.email),
+        })
+    }
+
+    /// traQ user name of the github user `login`.
+    pub fn traq(&self, login: &str) -> Option<&str> {
+        self.github.get(&login.to_lowercase()).map(String::as_str)
+    }
+
+    /// traQ user name of a commit author, looked up by the login and then by the email.
+    pub fn author(&self, login: Option<&str>, email: &str) -> Option<&str> {
+        login
+            .and_then(|login| self.traq(login))
+            .or_else(|| self.email.get(&email.to_lowercase()).map(String::as_str))
+    }
+
+    /// `@name` of the traQ user, which notifies them, or the github login in code.
+    pub fn mention(&self, login: &str) -> String {
+        match self.traq(login) {
+            Some(name) => format!("@{}", name),
+            None => format!("`{}`", login),
+        }
+    }
+
+    /// the traQ user name, or the github login, in code so that nobody is notified.
+    pub fn name(&self, login: &str) -> String {
+        format!("`{}`", self.traq(login).unwrap_or(login))
+    }
+}
@@ -1,7 +1,12 @@
+use crate::config::LimitsConfig;
+use crate::text;
+use crate::users::UserMap;
 use github_webhook::event::{
-    self, IssueCommentEvent, IssuesEvent, PullRequestEvent, PullRequestReviewCommentEvent,
-    PullRequestReviewEvent, PushEvent,
+    self, CreateEvent, DeleteEvent, DiscussionCommentEvent, DiscussionEvent, ForkEvent,
+    IssueCommentEvent, IssuesEvent, PullRequestEvent, PullRequestReviewCommentEvent,
+    PullRequestReviewEvent, PushEvent, ReleaseEvent, StarEvent, WorkflowJobEvent, WorkflowRunEvent,
 };
+use serde::Serialize;
 
 pub(crate) mod hidden {
     pub trait Marker {}
@@ -9,11 +14,15 @@ pub(crate) mod hidden {
 
 pub mod prelude {
     pub use super::{
-        action::TAction, assignee::TAssignee, comment::TComment, commit::TCommit, issue::TIssue,
-        label::TLabel, pull_request::TPullRequest, repository::TRepository, review::TReview,
+        action::TAction, assignee::TAssignee, comment::TComment, commit::TCommit,
+        discussion::TDiscussion, git_ref::TGitRef, issue::TIssue, label::TLabel,
+        pull_request::TPullRequest, release::TRelease, repository::TRepository, review::TReview,
+        workflow::TWorkflow,
     };
     pub use super::{
-        EIssueComment, EIssues, EPullRequest, EPullRequestReview, EPullRequestReviewComment, EPush,
+        ECreate, EDelete, EDiscussion, EDiscussionComment, EFork, EIssueComment, EIssues,
+        EPullRequest, EPullRequestReview, EPullRequestReviewComment, EPush, ERelease, EStar,
+        EWorkflowJob, EWorkflowRun,
     };
 }
 
@@ -45,16 +54,27 @@ newtype! {
     EPullRequest, PullRequestEvent,
     EPullRequestReviewComment,  PullRequestReviewCommentEvent,
     EPullRequestReview, PullRequestReviewEvent,
-    EPush, PushEvent
+    EPush, PushEvent,
+    ERelease, ReleaseEvent,
+    ECreate, CreateEvent,
+    EDelete, DeleteEvent,
+    EWorkflowRun, WorkflowRunEvent,
+    EWorkflowJob, WorkflowJobEvent,
+    EDiscussion, DiscussionEvent,
+    EDiscussionComment, DiscussionCommentEvent,
+    EStar, StarEvent,
+    EFork, ForkEvent
 }
 
 pub mod issue {
     use super::{hidden::Marker, *};
 
+    #[derive(Serialize)]
     pub struct Issue {
         num: u64,
         title: String,
         url: String,
+        #[serde(rename = "assignees")]
         _assignees: Vec<String>,
     }
 
@@ -62,6 +82,15 @@ pub mod issue {
         pub fn link_md(&self) -> String {
             format!("[#{} {}]({})", self.num, self.title, self.url)
         }
+
+        /// `#<num> <title>` without the link.
+        pub fn title(&self) -> String {
+            format!("#{} {}", self.num, self.title)
+        }
+
+        pub fn url(&self) -> &str {
+            &self.url
+        }
     }
 
     pub trait TIssue: Marker {
@@ -80,7 +109,7 @@ pub mod issue {
                     .collect(),
                 num: self.0.issue.number,
                 url: self.0.issue.html_url.clone(),
-                title: self.0.issue.title.clone(),
+                title: text::neutralize(&self.0.issue.title),
             };
             Some(issue)
         }
@@ -98,16 +127,74 @@ pub mod issue {
                     .collect(),
                 num: self.0.issue.number,
                 url: self.0.issue.html_url.clone(),
-                title: self.0.issue.title.clone(),
+                title: text::neutralize(&self.0.issue.title),
             };
             Some(issue)
         }
     }
 }
 
+pub mod discussion {
+    use super::{hidden::Marker, *};
+
+    #[derive(Serialize)]
+    pub struct Discussion {
+        num: u64,
+        title: String,
+        url: String,
+        category: String,
+    }
+
+    impl Discussion {
+        pub fn link_md(&self) -> String {
+            format!("[#{} {}]({})", self.num, self.title, self.url)
+        }
+
+        /// `#<num> <title>` without the link.
+        pub fn title(&self) -> String {
+            format!("#{} {}", self.num, self.title)
+        }
+
+        pub fn url(&self) -> &str {
+            &self.url
+        }
+
+        pub fn category_md(&self) -> String {
+            format!("Category: {}", self.category)
+        }
+    }
+
+    pub trait TDiscussion: Marker {
+        fn discussion(&self) -> Option<Discussion>;
+    }
+
+    impl From<&event::Discussion> for Discussion {
+        fn from(from: &event::Discussion) -> Self {
+            Discussion {
+                num: from.number,
+                title: text::neutralize(&from.title),
+                url: from.html_url.clone(),
+                category: from.category.name.clone(),
+            }
+        }
+    }
+
+    impl TDiscussion for EDiscussion {
+        fn discussion(&self) -> Option<Discussion> {
+            Some((&self.discussion).into())
+        }
+    }
+
+    impl TDiscussion for EDiscussionComment {
+        fn discussion(&self) -> Option<Discussion> {
+            Some((&self.discussion).into())
+        }
+    }
+}
+
 pub mod label {
     use super::{hidden::Marker, *};
-    #[derive(Debug, Clone)]
+    #[derive(Debug, Clone, Serialize)]
     pub struct Label {
         pub color: String,
         pub name: String,
@@ -118,6 +205,11 @@ pub mod label {
         pub fn md(&self) -> String {
             format!("[{}]({})", self.name, self.url)
         }
+
+        /// `color` as rgb, e.g. `d73a4a` -> `0xd73a4a`.
+        pub fn rgb(&self) -> Option<u32> {
+            u32::from_str_radix(self.color.trim_start_matches('#'), 16).ok()
+        }
     }
     pub trait TLabel: Marker {
         fn labels(&self) -> Vec<Label>;
@@ -145,10 +237,33 @@ pub mod label {
         }
     }
 
+    impl TLabel for EDiscussion {
+        fn labels(&self) -> Vec<Label> {
+            self.discussion.labels.iter().map(|l| l.into()).collect()
+        }
+    }
+
+    impl TLabel for EDiscussionComment {
+        fn labels(&self) -> Vec<Label> {
+            self.discussion.labels.iter().map(|l| l.into()).collect()
+        }
+    }
+
     impl TLabel for EPullRequest {
         fn labels(&self) -> Vec<Label> {
-            // todo
-            Vec::new()
+            self.pull_request.labels.iter().map(|l| l.into()).collect()
+        }
+    }
+
+    impl TLabel for EPullRequestReview {
+        fn labels(&self) -> Vec<Label> {
+            self.pull_request.labels.iter().map(|l| l.into()).collect()
+        }
+    }
+
+    impl TLabel for EPullRequestReviewComment {
+        fn labels(&self) -> Vec<Label> {
+            self.pull_request.labels.iter().map(|l| l.into()).collect()
         }
     }
 }
@@ -156,16 +271,117 @@ pub mod label {
 pub mod pull_request {
     use super::{hidden::Marker, *};
 
+    #[derive(Serialize)]
     pub struct PullRequest {
         num: u64,
         title: String,
         url: String,
+        draft: bool,
+        /// branch the pull request is merged into.
+        base: String,
+        head: String,
+        additions: u64,
+        deletions: u64,
+        changed_files: u64,
+        milestone: Option<Milestone>,
+        /// logins of the requested reviewers.
+        reviewers: Vec<String>,
+        /// names of the requested teams.
+        teams: Vec<String>,
+    }
+
+    #[derive(Serialize)]
+    pub struct Milestone {
+        title: String,
+        url: String,
+    }
+
+    impl From<&event::PullRequest> for PullRequest {
+        fn from(from: &event::PullRequest) -> Self {
+            PullRequest {
+                num: from.number,
+                title: text::neutralize(&from.title),
+                url: from.html_url.clone(),
+                draft: from.draft,
+                base: from.base._ref.clone(),
+                head: from.head._ref.clone(),
+                additions: from.additions,
+                deletions: from.deletions,
+                changed_files: from.changed_files,
+                milestone: from.milestone.as_ref().map(|m| Milestone {
+                    title: text::neutralize(&m.title),
+                    url: m.html_url.clone(),
+                }),
+                reviewers: from
+                    .requested_reviewers
+                    .iter()
+                    .map(|r| r.login.clone())
+                    .collect(),
+                teams: from
+                    .requested_teams
+                    .iter()
+                    .map(|t| t.name.clone())
+                    .collect(),
+            }
+        }
     }
 
     impl PullRequest {
         pub fn link_md(&self) -> String {
             format!("[#{} {}]({})", self.num, self.title, self.url)
         }
+
+        /// `#<num> <title>` without the link.
+        pub fn title(&self) -> String {
+            format!("#{} {}", self.num, self.title)
+        }
+
+        pub fn url(&self) -> &str {
+            &self.url
+        }
+
+        pub fn is_draft(&self) -> bool {
+            self.draft
+        }
+
+        /// `` `base` ← `head` ``
+        pub fn branches_md(&self) -> String {
+            format!("`{}` ← `{}`", self.base, self.head)
+        }
+
+        /// `+10 -2 in 3 files`
+        pub fn diff_md(&self) -> String {
+            let files = if self.changed_files == 1 {
+                "file"
+            } else {
+                "files"
+            };
+            format!(
+                "+{} -{} in {} {}",
+                self.additions, self.deletions, self.changed_files, files
+            )
+        }
+
+        pub fn milestone_md(&self) -> Option<String> {
+            self.milestone
+                .as_ref()
+                .map(|m| format!("[{}]({})", m.title, m.url))
+        }
+
+        /// requested reviewers and teams, without notifying them.
+        pub fn reviewers_md(&self, users: &UserMap) -> Option<String> {
+            let reviewers: Vec<String> = self
+                .reviewers
+                .iter()
+                .map(|r| users.name(r))
+                .chain(self.teams.iter().map(|t| format!("`{}`", t)))
+                .collect();
+            if reviewers.is_empty() {
+                None
+            } else {
+                Some(reviewers.join(", "))
+            }
+        }
     }
 
     pub trait TPullRequest: Marker {
@@ -174,34 +390,19 @@ pub mod pull_request {
 
     impl TPullRequest for EPullRequest {
         fn pr(&self) -> Option<PullRequest> {
-            let pr = PullRequest {
-                num: self.number,
-                title: self.0.pull_request.title.clone(),
-                url: self.0.pull_request.url.clone(),
-            };
-            Some(pr)
+            Some((&self.pull_request).into())
         }
     }
 
     impl TPullRequest for EPullRequestReview {
         fn pr(&self) -> Option<PullRequest> {
-            let pr = PullRequest {
-                num: self.pull_request.number,
-                title: self.pull_request.title.clone(),
-                url: self.pull_request.html_url.clone(),
-            };
-            Some(pr)
+            Some((&self.pull_request).into())
         }
     }
 
     impl TPullRequest for EPullRequestReviewComment {
         fn pr(&self) -> Option<PullRequest> {
-            let pr = PullRequest {
-                num: self.pull_request.number,
-                title: self.pull_request.title.clone(),
-                url: self.pull_request.html_url.clone(),
-            };
-            Some(pr)
+            Some((&self.pull_request).into())
         }
     }
 }
@@ -209,6 +410,7 @@ pub mod pull_request {
 pub mod assignee {
     use super::{hidden::Marker, *};
 
+    #[derive(Serialize)]
     pub struct Assignee {
         pub name: String,
     }
@@ -217,8 +419,8 @@ pub mod assignee {
             Self { name: name.into() }
         }
 
-        pub fn md(&self) -> String {
-            self.name.clone()
+        pub fn md(&self, users: &UserMap) -> String {
+            users.name(&self.name)
         }
     }
 
@@ -280,15 +482,26 @@ pub mod assignee {
 pub mod repository {
     use super::{hidden::Marker, *};
 
+    #[derive(Serialize)]
     pub struct Repository {
         name: String,
         owner: String,
+        /// `owner/repo`, which routes and filters are matched against.
+        full_name: String,
         url: String,
     }
 
     impl Repository {
         pub fn link_md(&self) -> String {
-            format!("[{}/{}]({})", self.owner, self.name, self.url)
+            format!("[{}]({})", self.full_name, self.url)
+        }
+
+        pub fn full_name(&self) -> String {
+            self.full_name.clone()
+        }
+
+        pub fn url(&self) -> &str {
+            &self.url
         }
     }
 
@@ -300,6 +513,7 @@ pub mod repository {
         fn repo(&self) -> Option<Repository> {
             let repo = Repository {
                 name: self.repository.name.clone(),
+                full_name: self.repository.full_name.clone(),
                 owner: self.repository.owner.login.clone(),
                 url: self.repository.html_url.clone(),
             };
@@ -311,6 +525,7 @@ pub mod repository {
         fn repo(&self) -> Option<Repository> {
             let repo = Repository {
                 name: self.repository.name.clone(),
+                full_name: self.repository.full_name.clone(),
                 owner: self.repository.owner.login.clone(),
                 url: self.repository.html_url.clone(),
             };
@@ -322,6 +537,7 @@ pub mod repository {
         fn repo(&self) -> Option<Repository> {
             let repo = Repository {
                 name: self.repository.name.clone(),
+                full_name: self.repository.full_name.clone(),
                 owner: self.repository.owner.login.clone(),
                 url: self.repository.html_url.clone(),
             };
@@ -333,6 +549,7 @@ pub mod repository {
         fn repo(&self) -> Option<Repository> {
             let repo = Repository {
                 name: self.repository.name.clone(),
+                full_name: self.repository.full_name.clone(),
                 owner: self.repository.owner.login.clone(),
                 url: self.repository.html_url.clone(),
             };
@@ -344,16 +561,126 @@ pub mod repository {
         fn repo(&self) -> Option<Repository> {
             let repo = Repository {
                 name: self.repository.name.clone(),
+                full_name: self.repository.full_name.clone(),
+                owner: self.repository.owner.login.clone(),
+                url: self.repository.html_url.clone(),
+            };
+            Some(repo)
+        }
+    }
+    impl TRepository for ERelease {
+        fn repo(&self) -> Option<Repository> {
+            let repo = Repository {
+                name: self.repository.name.clone(),
+                full_name: self.repository.full_name.clone(),
+                owner: self.repository.owner.login.clone(),
+                url: self.repository.html_url.clone(),
+            };
+            Some(repo)
+        }
+    }
+
+    impl TRepository for ECreate {
+        fn repo(&self) -> Option<Repository> {
+            let repo = Repository {
+                name: self.repository.name.clone(),
+                full_name: self.repository.full_name.clone(),
+                owner: self.repository.owner.login.clone(),
+                url: self.repository.html_url.clone(),
+            };
+            Some(repo)
+        }
+    }
+
+    impl TRepository for EDelete {
+        fn repo(&self) -> Option<Repository> {
+            let repo = Repository {
+                name: self.repository.name.clone(),
+                full_name: self.repository.full_name.clone(),
+                owner: self.repository.owner.login.clone(),
+                url: self.repository.html_url.clone(),
+            };
+            Some(repo)
+        }
+    }
+
+    impl TRepository for EWorkflowRun {
+        fn repo(&self) -> Option<Repository> {
+            let repo = Repository {
+                name: self.repository.name.clone(),
+                full_name: self.repository.full_name.clone(),
+                owner: self.repository.owner.login.clone(),
+                url: self.repository.html_url.clone(),
+            };
+            Some(repo)
+        }
+    }
+
+    impl TRepository for EWorkflowJob {
+        fn repo(&self) -> Option<Repository> {
+            let repo = Repository {
+                name: self.repository.name.clone(),
+                full_name: self.repository.full_name.clone(),
+                owner: self.repository.owner.login.clone(),
+                url: self.repository.html_url.clone(),
+            };
+            Some(repo)
+        }
+    }
+
+    impl TRepository for EDiscussion {
+        fn repo(&self) -> Option<Repository> {
+            let repo = Repository {
+                name: self.repository.name.clone(),
+                full_name: self.repository.full_name.clone(),
+                owner: self.repository.owner.login.clone(),
+                url: self.repository.html_url.clone(),
+            };
+            Some(repo)
+        }
+    }
+
+    impl TRepository for EDiscussionComment {
+        fn repo(&self) -> Option<Repository> {
+            let repo = Repository {
+                name: self.repository.name.clone(),
+                full_name: self.repository.full_name.clone(),
                 owner: self.repository.owner.login.clone(),
                 url: self.repository.html_url.clone(),
             };
             Some(repo)
         }
     }
+
+    impl TRepository for EStar {
+        fn repo(&self) -> Option<Repository> {
+            let repo = Repository {
+                name: self.repository.name.clone(),
+                full_name: self.repository.full_name.clone(),
+                owner: self.repository.owner.login.clone(),
+                url: self.repository.html_url.clone(),
+            };
+            Some(repo)
+        }
+    }
+
+    impl TRepository for EFork {
+        fn repo(&self) -> Option<Repository> {
+            let repo = Repository {
+                name: self.repository.name.clone(),
+                full_name: self.repository.full_name.clone(),
+                owner: self.repository.owner.login.clone(),
+                url: self.repository.html_url.clone(),
+            };
+            Some(repo)
+        }
+    }
+
     impl TRepository for EPush {
         fn repo(&self) -> Option<Repository> {
             let repo = Repository {
                 name: self.repository.name.clone(),
+                full_name: self.repository.full_name.clone(),
                 owner: self.repository.owner.name.clone(),
                 url: self.repository.html_url.clone(),
             };
@@ -365,18 +692,48 @@ pub mod repository {
 pub mod action {
     use super::{hidden::Marker, *};
 
+    #[derive(Serialize)]
     pub struct Action {
         action: String,
+        /// machine readable name of the action, e.g. `opened`, `merged`.
+        key: String,
         sender: String,
         assignee: Option<String>,
     }
 
-    impl Action {
-        pub fn md(&self) -> String {
-            if let Some(ref assignee) = self.assignee {
-                format!("{} to `{}` by `{}`", self.action, assignee, self.sender)
+    /// `ReviewRequested` -> `review_requested`
+    fn snake_case(s: &str) -> String {
+        let mut out = String::with_capacity(s.len() + 4);
+        for (i, c) in s.chars().enumerate() {
+            if c.is_uppercase() {
+                if i != 0 {
+                    out.push('_');
+                }
+                out.extend(c.to_lowercase());
             } else {
-                format!("{} by `{}`", self.action, self.sender)
+                out.push(c);
+            }
+        }
+        out
+    }
+
+    impl Action {
+        pub fn key(&self) -> &str {
+            &self.key
+        }
+
+        /// the assignee is mentioned when they are assigned or requested for review.
+        pub fn md(&self, users: &UserMap) -> String {
+            let sender = users.name(&self.sender);
+            match self.assignee {
+                Some(ref assignee) => {
+                    let assignee = match self.key.as_str() {
+                        "assigned" | "review_requested" => users.mention(assignee),
+                        _ => users.name(assignee),
+                    };
+                    format!("{} to {} by {}", self.action, assignee, sender)
+                }
+                None => format!("{} by {}", self.action, sender),
             }
         }
     }
@@ -389,11 +746,13 @@ pub mod action {
         fn action(&self) -> Option<Action> {
             use github_webhook::event::IssuesAction::*;
             let assignee = match self.action {
-                Assigned | Unassigned => self.issue.assignee.as_ref().map(|v| v.login.clone()),
+                // the user of this assignment, not the first assignee of the issue
+                Assigned | Unassigned => self.assignee.as_ref().map(|v| v.login.clone()),
                 _ => None,
             };
             let action = Action {
                 action: format!("{:?}", self.action),
+                key: snake_case(&format!("{:?}", self.action)),
                 sender: self.sender.login.clone(),
                 assignee,
             };
@@ -406,6 +765,7 @@ pub mod action {
             let assignee = self.issue.assignee.as_ref().map(|v| v.login.clone());
             let action = Action {
                 action: format!("{:?}", self.action),
+                key: snake_case(&format!("{:?}", self.action)),
                 sender: self.sender.login.clone(),
                 assignee,
             };
@@ -420,7 +780,17 @@ pub mod action {
                 Opened | Edited | Closed | Reopened | Assigned | Unassigned | ReviewRequested
                 | ReviewRequestRemoved | ReadyForReview | Labeled | Unlabeled | Locked
                 | Unlocked => {
-                    let assignee = self.pull_request.assignee.as_ref().map(|v| v.login.clone());
+                    let assignee = match self.action {
+                        // the user or the team of this request
+                        ReviewRequested | ReviewRequestRemoved => self
+                            .requested_reviewer
+                            .as_ref()
+                            .map(|v| v.login.clone())
+                            .or_else(|| self.requested_team.as_ref().map(|t| t.name.clone())),
+                        // the user of this assignment
+                        Assigned | Unassigned => self.assignee.as_ref().map(|v| v.login.clone()),
+                        _ => self.pull_request.assignee.as_ref().map(|v| v.login.clone()),
+                    };
                     let action = if let event::PullRequestAction::Closed = self.action {
                         if self.pull_request.merged {
                             "Merged".to_owned()
@@ -431,6 +801,7 @@ pub mod action {
                         format!("{:?}", self.action)
                     };
                     let action = Action {
+                        key: snake_case(&action),
                         action,
                         sender: self.sender.login.clone(),
                         assignee,
@@ -447,6 +818,7 @@ pub mod action {
             let assignee = self.pull_request.assignee.as_ref().map(|v| v.login.clone());
             let action = Action {
                 action: format!("{:?}", self.action),
+                key: snake_case(&format!("{:?}", self.action)),
                 sender: self.sender.login.clone(),
                 assignee,
             };
@@ -459,6 +831,7 @@ pub mod action {
             let assignee = self.pull_request.assignee.as_ref().map(|v| v.login.clone());
             let action = Action {
                 action: format!("{:?}", self.action),
+                key: snake_case(&format!("{:?}", self.action)),
                 sender: self.sender.login.clone(),
                 assignee,
             };
@@ -466,20 +839,184 @@ pub mod action {
         }
     }
 
-    impl TAction for EPush {
+    impl TAction for ERelease {
         fn action(&self) -> Option<Action> {
-            if self.commits.len() < 1 {
-                return None;
+            use github_webhook::event::ReleaseAction::*;
+            match self.action {
+                // github sends `prereleased` together with `published` for a pre-release
+                Published | Edited | Deleted => {
+                    let action = Action {
+                        action: format!("{:?}", self.action),
+                        key: snake_case(&format!("{:?}", self.action)),
+                        sender: self.release.author.login.clone(),
+                        assignee: None,
+                    };
+                    Some(action)
+                }
+                _ => None,
             }
+        }
+    }
 
-            let action = if self.commits.len() > 1 {
-                format!("{} commits pushed to {}", self.commits.len(), self._ref)
+    impl TAction for ECreate {
+        fn action(&self) -> Option<Action> {
+            let action = Action {
+                action: "Created".to_owned(),
+                key: "created".to_owned(),
+                sender: self.sender.login.clone(),
+                assignee: None,
+            };
+            Some(action)
+        }
+    }
+
+    impl TAction for EDelete {
+        fn action(&self) -> Option<Action> {
+            let action = Action {
+                action: "Deleted".to_owned(),
+                key: "deleted".to_owned(),
+                sender: self.sender.login.clone(),
+                assignee: None,
+            };
+            Some(action)
+        }
+    }
+
+    impl TAction for EWorkflowRun {
+        fn action(&self) -> Option<Action> {
+            use super::workflow::conclusion_md;
+            let conclusion = self.workflow_run.conclusion.as_ref()?;
+            let action = Action {
+                action: format!(
+                    "{} on `{}`",
+                    conclusion_md(conclusion)?,
+                    self.workflow_run.head_branch
+                ),
+                key: conclusion.clone(),
+                sender: self.workflow_run.actor.login.clone(),
+                assignee: None,
+            };
+            Some(action)
+        }
+    }
+
+    impl TAction for EWorkflowJob {
+        fn action(&self) -> Option<Action> {
+            use super::workflow::conclusion_md;
+            let conclusion = self.workflow_job.conclusion.as_ref()?;
+            let action = Action {
+                action: conclusion_md(conclusion)?.to_owned(),
+                key: conclusion.clone(),
+                sender: self.sender.login.clone(),
+                assignee: None,
+            };
+            Some(action)
+        }
+    }
+
+    impl TAction for EDiscussion {
+        fn action(&self) -> Option<Action> {
+            let action = Action {
+                action: format!("{:?}", self.action),
+                key: snake_case(&format!("{:?}", self.action)),
+                sender: self.sender.login.clone(),
+                assignee: None,
+            };
+            Some(action)
+        }
+    }
+
+    impl TAction for EDiscussionComment {
+        fn action(&self) -> Option<Action> {
+            let action = Action {
+                action: format!("{:?}", self.action),
+                key: snake_case(&format!("{:?}", self.action)),
+                sender: self.sender.login.clone(),
+                assignee: None,
+            };
+            Some(action)
+        }
+    }
+
+    impl TAction for EStar {
+        fn action(&self) -> Option<Action> {
+            use github_webhook::event::StarAction::*;
+            match self.action {
+                Created => {
+                    let action = Action {
+                        action: "Starred".to_owned(),
+                        key: "created".to_owned(),
+                        sender: self.sender.login.clone(),
+                        assignee: None,
+                    };
+                    Some(action)
+                }
+                Deleted => None,
+            }
+        }
+    }
+
+    impl TAction for EFork {
+        fn action(&self) -> Option<Action> {
+            let action = Action {
+                action: "Forked".to_owned(),
+                key: "forked".to_owned(),
+                sender: self.sender.login.clone(),
+                assignee: Some(format!("{}/{}", self.forkee.owner.login, self.forkee.name)),
+            };
+            Some(action)
+        }
+    }
+
+    impl TAction for EPush {
+        fn action(&self) -> Option<Action> {
+            use super::git_ref::TGitRef;
+
+            let git_ref = self.git_ref();
+            let name = match git_ref {
+                Some(ref git_ref) => git_ref.link_md(),
+                None => format!("`{}`", self._ref),
+            };
+            let commits = match self.commits.len() {
+                1 => format!("[1 commit]({})", self.compare),
+                n if n >= super::commit::GITHUB_COMMITS_LIMIT => {
+                    format!("[{}+ commits]({})", n, self.compare)
+                }
+                n => format!("[{} commits]({})", n, self.compare),
+            };
+
+            let (action, key) = if self.deleted {
+                let deleted = match git_ref {
+                    Some(git_ref) => format!("{} deleted", git_ref.md()),
+                    None => format!("{} deleted", name),
+                };
+                (deleted, "deleted")
+            } else if self.created {
+                let mut created = match git_ref {
+                    Some(git_ref) => format!("{} created", git_ref.md()),
+                    None => format!("{} created", name),
+                };
+                if !self.commits.is_empty() {
+                    created += &format!(" with {}", commits);
+                }
+                (created, "created")
+            } else if self.forced {
+                let forced = if self.commits.is_empty() {
+                    // reset to an older commit
+                    format!("[Force-pushed]({}) to {}", self.compare, name)
+                } else {
+                    format!("{} force-pushed to {}", commits, name)
+                };
+                (forced, "forced")
+            } else if self.commits.is_empty() {
+                return None;
             } else {
-                format!("{} commit pushed to {}", self.commits.len(), self._ref)
+                (format!("{} pushed to {}", commits, name), "pushed")
             };
 
             let action = Action {
-                action: action,
+                action,
+                key: key.to_owned(),
                 sender: self.sender.login.clone(),
                 assignee: None,
             };
@@ -491,8 +1028,16 @@ pub mod action {
 pub mod commit {
     use super::{hidden::Marker, *};
 
+    /// github sends at most this many commits in a push payload.
+    pub const GITHUB_COMMITS_LIMIT: usize = 2048;
+
+    #[derive(Serialize)]
     pub struct Commit {
         author: String,
+        /// github login of the author, if the email belongs to an account.
+        username: Option<String>,
+        #[serde(skip)]
+        email: String,
         time: String,
         message: String,
         id: String,
@@ -500,17 +1045,39 @@ pub mod commit {
     }
 
     impl Commit {
-        pub fn md(&self) -> String {
+        /// the first line of the message.
+        pub fn subject(&self) -> &str {
+            self.message.lines().next().unwrap_or("")
+        }
+
+        /// whether the commit was made by `git merge` or by merging a pull request.
+        pub fn is_merge(&self) -> bool {
+            let subject = self.subject();
+            [
+                "Merge pull request ",
+                "Merge branch ",
+                "Merge remote-tracking branch ",
+                "Merge tag ",
+            ]
+            .iter()
+            .any(|prefix| subject.starts_with(prefix))
+        }
+
+        pub fn md(&self, users: &UserMap) -> String {
             let id: String = self.id.chars().take(7).collect();
-            format!(
-                "[{}]({}) - {} {} {}",
-                id, self.url, self.message, self.time, self.author
-            )
+            let author = users
+                .author(self.username.as_deref(), &self.email)
+                .unwrap_or(&self.author);
+            format!("[{}]({}) - {} {}", id, self.url, self.subject(), author)
         }
     }
 
     pub trait TCommit: Marker {
         fn commits(&self) -> Vec<Commit>;
+        /// page which shows every commit.
+        fn commits_url(&self) -> Option<String>;
+        /// whether github left out commits from the payload.
+        fn commits_truncated(&self) -> bool;
     }
 
     impl TCommit for EPush {
@@ -524,32 +1091,70 @@ pub mod commit {
                     .unwrap_or("time parse error".to_string());
                 commits.push(Commit {
                     author: commit.author.name.clone(),
+                    username: commit.author.username.clone(),
+                    email: commit.author.email.clone(),
                     time,
-                    message: commit.message.clone(),
+                    message: text::neutralize(&commit.message),
                     id: commit.id.clone(),
                     url: commit.url.clone(),
                 });
             }
             commits
         }
+
+        fn commits_url(&self) -> Option<String> {
+            Some(self.compare.clone())
+        }
+
+        /// the payload does not tell the number of commits,
+        /// so a push of exactly `GITHUB_COMMITS_LIMIT` commits counts as truncated.
+        fn commits_truncated(&self) -> bool {
+            self.commits.len() >= GITHUB_COMMITS_LIMIT
+        }
     }
 }
 
 pub mod comment {
     use super::{hidden::Marker, *};
 
+    #[derive(Serialize)]
     pub struct Comment {
         comment: String,
         sender: String,
+        /// where the whole comment is shown.
+        url: String,
     }
 
     impl Comment {
-        pub fn comment(&self) -> String {
-            self.comment.clone()
+        /// the body made safe to post with `text::sanitize`.
+        pub fn comment(&self, users: &UserMap) -> String {
+            text::sanitize(&self.comment, users)
+        }
+
+        /// the sanitized body cut to the limits, with a link to the whole comment.
+        pub fn body(&self, users: &UserMap, limits: &LimitsConfig) -> String {
+            text::truncate_with_link(
+                &self.comment(users),
+                limits.body_chars,
+                limits.body_lines,
+                &self.url,
+            )
+        }
+
+        /// the comment with `body` in place of the raw body, for templates.
+        pub fn sanitized(self, users: &UserMap, limits: &LimitsConfig) -> Self {
+            Self {
+                comment: self.body(users, limits),
+                ..self
+            }
+        }
+
+        pub fn url(&self) -> &str {
+            &self.url
         }
 
-        pub fn comment_with_sender(&self) -> String {
-            format!("{}: {}", self.sender, self.comment)
+        pub fn comment_with_sender(&self, users: &UserMap) -> String {
+            format!("{}: {}", self.sender, self.comment(users))
         }
     }
 
@@ -562,6 +1167,7 @@ pub mod comment {
             self.issue.body.as_ref().map(|body| Comment {
                 comment: body.clone(),
                 sender: self.sender.login.clone(),
+                url: self.issue.html_url.clone(),
             })
         }
     }
@@ -571,6 +1177,7 @@ pub mod comment {
             Some(Comment {
                 comment: self.comment.body.clone(),
                 sender: self.sender.login.clone(),
+                url: self.comment.html_url.clone(),
             })
         }
     }
@@ -580,6 +1187,27 @@ pub mod comment {
             self.pull_request.body.as_ref().map(|body| Comment {
                 comment: body.clone(),
                 sender: self.sender.login.clone(),
+                url: self.pull_request.html_url.clone(),
+            })
+        }
+    }
+
+    impl TComment for EDiscussion {
+        fn comment(&self) -> Option<Comment> {
+            self.discussion.body.as_ref().map(|body| Comment {
+                comment: body.clone(),
+                sender: self.sender.login.clone(),
+                url: self.discussion.html_url.clone(),
+            })
+        }
+    }
+
+    impl TComment for EDiscussionComment {
+        fn comment(&self) -> Option<Comment> {
+            Some(Comment {
+                comment: self.comment.body.clone(),
+                sender: self.sender.login.clone(),
+                url: self.comment.html_url.clone(),
             })
         }
     }
@@ -589,6 +1217,7 @@ pub mod comment {
             self.review.body.as_ref().map(|body| Comment {
                 comment: body.clone(),
                 sender: self.sender.login.clone(),
+                url: self.review.html_url.clone(),
             })
         }
     }
@@ -598,16 +1227,296 @@ pub mod comment {
             Some(Comment {
                 comment: self.comment.body.clone(),
                 sender: self.sender.login.clone(),
+                url: self.comment.html_url.clone(),
             })
         }
     }
 }
 
+pub mod release {
+    use super::{hidden::Marker, *};
+
+    #[derive(Serialize)]
+    pub struct Release {
+        tag: String,
+        name: Option<String>,
+        url: String,
+        body: Option<String>,
+        prerelease: bool,
+    }
+
+    impl Release {
+        pub fn link_md(&self) -> String {
+            match self.name {
+                Some(ref name) if !name.is_empty() && name != &self.tag => {
+                    format!("[{} {}]({})", self.tag, name, self.url)
+                }
+                _ => format!("[{}]({})", self.tag, self.url),
+            }
+        }
+
+        pub fn body(&self, users: &UserMap) -> Option<String> {
+            self.body
+                .as_ref()
+                .map(|b| text::sanitize(b, users))
+                .filter(|b| !b.is_empty())
+        }
+
+        /// the sanitized body cut to the limits, with a link to the whole release.
+        pub fn truncated_body(&self, users: &UserMap, limits: &LimitsConfig) -> Option<String> {
+            self.body(users).map(|body| {
+                text::truncate_with_link(&body, limits.body_chars, limits.body_lines, &self.url)
+            })
+        }
+
+        /// the release with `truncated_body` in place of the raw body, for templates.
+        pub fn sanitized(self, users: &UserMap, limits: &LimitsConfig) -> Self {
+            Self {
+                body: self.truncated_body(users, limits),
+                ..self
+            }
+        }
+
+        pub fn url(&self) -> &str {
+            &self.url
+        }
+    }
+
+    pub trait TRelease: Marker {
+        fn release(&self) -> Option<Release>;
+    }
+
+    impl TRelease for ERelease {
+        fn release(&self) -> Option<Release> {
+            let release = Release {
+                tag: text::neutralize(&self.release.tag_name),
+                name: self.release.name.as_deref().map(text::neutralize),
+                url: self.release.html_url.clone(),
+                body: self.release.body.clone(),
+                prerelease: self.release.prerelease,
+            };
+            Some(release)
+        }
+    }
+}
+
+pub mod git_ref {
+    use super::{hidden::Marker, *};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum RefKind {
+        Branch,
+        Tag,
+    }
+
+    #[derive(Serialize)]
+    pub struct GitRef {
+        kind: RefKind,
+        name: String,
+        url: Option<String>,
+    }
+
+    impl GitRef {
+        fn new(ref_type: &str, name: &str, repo_url: &str, exists: bool) -> Option<Self> {
+            let kind = match ref_type {
+                "branch" => RefKind::Branch,
+                "tag" => RefKind::Tag,
+                _ => return None,
+            };
+            let url = if exists {
+                let url = match kind {
+                    RefKind::Branch => format!("{}/tree/{}", repo_url, name),
+                    RefKind::Tag => format!("{}/releases/tag/{}", repo_url, name),
+                };
+                Some(url)
+            } else {
+                None
+            };
+            Some(GitRef {
+                kind,
+                name: name.to_owned(),
+                url,
+            })
+        }
+
+        pub fn kind(&self) -> RefKind {
+            self.kind
+        }
+
+        pub fn is_semver(&self) -> bool {
+            self.kind == RefKind::Tag && is_semver(&self.name)
+        }
+
+        /// the name, linked to the branch or tag if it exists.
+        pub fn link_md(&self) -> String {
+            match self.url {
+                Some(ref url) => format!("[`{}`]({})", self.name, url),
+                None => format!("`{}`", self.name),
+            }
+        }
+
+        pub fn md(&self) -> String {
+            let kind = match self.kind {
+                RefKind::Branch => "Branch",
+                RefKind::Tag if self.is_semver() => "Version tag",
+                RefKind::Tag => "Tag",
+            };
+            format!("{} {}", kind, self.link_md())
+        }
+    }
+
+    /// `1.2.3`, `v1.2.3`, `v1.2.3-rc.1`, `1.2.3+build`...
+    pub fn is_semver(tag: &str) -> bool {
+        let tag = tag.strip_prefix('v').unwrap_or(tag);
+        let core = tag.split(|c| c == '-' || c == '+').next().unwrap_or("");
+        let parts: Vec<&str> = core.split('.').collect();
+        parts.len() == 3
+            && parts
+                .iter()
+                .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+    }
+
+    pub trait TGitRef: Marker {
+        fn git_ref(&self) -> Option<GitRef>;
+    }
+
+    impl TGitRef for ECreate {
+        fn git_ref(&self) -> Option<GitRef> {
+            GitRef::new(&self.ref_type, &self._ref, &self.repository.html_url, true)
+        }
+    }
+
+    impl TGitRef for EDelete {
+        fn git_ref(&self) -> Option<GitRef> {
+            GitRef::new(&self.ref_type, &self._ref, &self.repository.html_url, false)
+        }
+    }
+
+    impl TGitRef for EPush {
+        /// `None` for refs other than branches and tags.
+        fn git_ref(&self) -> Option<GitRef> {
+            let (ref_type, name) = match self._ref.strip_prefix("refs/heads/") {
+                Some(name) => ("branch", name),
+                None => ("tag", self._ref.strip_prefix("refs/tags/")?),
+            };
+            GitRef::new(ref_type, name, &self.repository.html_url, !self.deleted)
+        }
+    }
+}
+
+pub mod workflow {
+    use super::{hidden::Marker, *};
+
+    #[derive(Serialize)]
+    pub struct Workflow {
+        name: String,
+        url: String,
+        conclusion: Option<String>,
+        head_sha: String,
+        head_message: Option<String>,
+        head_author: Option<String>,
+        #[serde(skip)]
+        head_author_username: Option<String>,
+        #[serde(skip)]
+        head_author_email: Option<String>,
+        commit_url: String,
+    }
+
+    /// a successful run is only reported when it fixes a failed one.
+    pub fn conclusion_md(conclusion: &str) -> Option<&'static str> {
+        match conclusion {
+            "failure" => Some("Failed"),
+            "cancelled" => Some("Cancelled"),
+            "timed_out" => Some("Timed out"),
+            "success" => Some("Fixed"),
+            _ => None,
+        }
+    }
+
+    impl Workflow {
+        pub fn link_md(&self) -> String {
+            format!("[{}]({})", self.name, self.url)
+        }
+
+        pub fn is_failure(&self) -> bool {
+            matches!(
+                self.conclusion.as_deref(),
+                Some("failure") | Some("cancelled") | Some("timed_out")
+            )
+        }
+
+        pub fn is_success(&self) -> bool {
+            self.conclusion.as_deref() == Some("success")
+        }
+
+        pub fn head_commit_md(&self, users: &UserMap) -> String {
+            let id: String = self.head_sha.chars().take(7).collect();
+            let mut md = format!("[{}]({})", id, self.commit_url);
+            if let Some(ref message) = self.head_message {
+                md += &format!(" - {}", message.lines().next().unwrap_or(""));
+            }
+            if let Some(ref author) = self.head_author {
+                let author = self
+                    .head_author_email
+                    .as_ref()
+                    .and_then(|email| users.author(self.head_author_username.as_deref(), email))
+                    .unwrap_or(author);
+                md += &format!(" {}", author);
+            }
+            md
+        }
+    }
+
+    pub trait TWorkflow: Marker {
+        fn workflow(&self) -> Option<Workflow>;
+    }
+
+    impl TWorkflow for EWorkflowRun {
+        fn workflow(&self) -> Option<Workflow> {
+            let run = &self.workflow_run;
+            let workflow = Workflow {
+                name: format!("{} #{}", run.name, run.run_number),
+                url: run.html_url.clone(),
+                conclusion: run.conclusion.clone(),
+                head_sha: run.head_sha.clone(),
+                head_message: Some(text::neutralize(&run.head_commit.message)),
+                head_author: Some(run.head_commit.author.name.clone()),
+                head_author_username: run.head_commit.author.username.clone(),
+                head_author_email: Some(run.head_commit.author.email.clone()),
+                commit_url: format!("{}/commit/{}", self.repository.html_url, run.head_sha),
+            };
+            Some(workflow)
+        }
+    }
+
+    impl TWorkflow for EWorkflowJob {
+        fn workflow(&self) -> Option<Workflow> {
+            let job = &self.workflow_job;
+            let workflow = Workflow {
+                name: job.name.clone(),
+                url: job.html_url.clone(),
+                conclusion: job.conclusion.clone(),
+                head_sha: job.head_sha.clone(),
+                head_message: None,
+                head_author: None,
+                head_author_username: None,
+                head_author_email: None,
+                commit_url: format!("{}/commit/{}", self.repository.html_url, job.head_sha),
+            };
+            Some(workflow)
+        }
+    }
+}
+
 pub mod review {
     use super::{hidden::Marker, *};
 
+    #[derive(Serialize)]
     pub struct Review {
+        #[serde(rename = "comment")]
         _comment: String,
+        #[serde(rename = "reviewer")]
         _reviewer: String,
         url: String,
     }
@@ -620,6 +1529,20 @@ pub mod review {
         pub fn review(&self, title: impl Into<String>) -> String {
             format!("[{}]({})", title.into(), self.url)
         }
+
+        /// the review with the comment sanitized and cut to the limits, for templates.
+        pub fn sanitized(self, users: &UserMap, limits: &LimitsConfig) -> Self {
+            let comment = text::sanitize(&self._comment, users);
+            Self {
+                _comment: text::truncate_with_link(
+                    &comment,
+                    limits.body_chars,
+                    limits.body_lines,
+                    &self.url,
+                ),
+                ..self
+            }
+        }
     }
 
     pub trait TReview: Marker {
@@ -1,58 +1,276 @@
 use actix_web::{HttpMessage, HttpRequest};
 
+use crate::config::{Config, HttpConfig, LimitsConfig, Route, Target, UnsupportedPolicy};
+use crate::delivery::Deliveries;
 use crate::error::MyError;
+use crate::filter::{self, Facts};
+use crate::outbox::Outbox;
+use crate::sink::{self, Post, Sink};
+use crate::store::MessageStore;
+use crate::template::Templates;
+use crate::users::UserMap;
+use chrono::Utc;
 use github_webhook::event::{self, Event};
-use hex::{FromHex, ToHex};
-use log::info;
-use reqwest::header::HeaderMap;
-use reqwest::Response;
+use hex::FromHex;
 use ring::{constant_time::verify_slices_are_equal, hmac};
-use serde_json;
-use std::sync::Arc;
+use serde_json::{self, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 const X_GITHUB_EVENT: &str = "X-Github-Event";
 const X_HUB_SIGNATURE: &str = "X-Hub-Signature-256";
+const X_HUB_SIGNATURE_SHA1: &str = "X-Hub-Signature";
+const X_GITHUB_DELIVERY: &str = "X-GitHub-Delivery";
+/// set to `true` to post a delivery which has already been processed.
+const X_FORCE_REPOST: &str = "X-Force-Repost";
 
-const X_TRAQ_SIGNATURE: &str = "X-TRAQ-Signature";
+const CONTENT_TYPE_JSON: &str = "application/json";
 
-fn generate_signature<'a>(message: &'a str, secret: &'a str) -> Vec<u8> {
-    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret.as_bytes());
-    let tag = hmac::sign(&key, message.as_bytes());
-    tag.as_ref().to_vec()
+/// HMAC used by the `X-Hub-Signature*` headers.
+#[derive(Debug, Clone, Copy)]
+enum SignatureAlgorithm {
+    Sha256,
+    /// legacy `X-Hub-Signature` header.
+    Sha1,
+}
+
+impl SignatureAlgorithm {
+    fn header(self) -> &'static str {
+        match self {
+            SignatureAlgorithm::Sha256 => X_HUB_SIGNATURE,
+            SignatureAlgorithm::Sha1 => X_HUB_SIGNATURE_SHA1,
+        }
+    }
+
+    fn prefix(self) -> &'static str {
+        match self {
+            SignatureAlgorithm::Sha256 => "sha256=",
+            SignatureAlgorithm::Sha1 => "sha1=",
+        }
+    }
+
+    fn hex_len(self) -> usize {
+        match self {
+            SignatureAlgorithm::Sha256 => 64,
+            SignatureAlgorithm::Sha1 => 40,
+        }
+    }
+
+    fn hmac(self) -> hmac::Algorithm {
+        match self {
+            SignatureAlgorithm::Sha256 => hmac::HMAC_SHA256,
+            SignatureAlgorithm::Sha1 => hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY,
+        }
+    }
+
+    /// checks that `signature` looks like `<prefix><hex digits>`.
+    fn validate(self, signature: &str) -> Result<(), MyError> {
+        match signature.strip_prefix(self.prefix()) {
+            Some(hex)
+                if hex.len() == self.hex_len() && hex.chars().all(|c| c.is_ascii_hexdigit()) =>
+            {
+                Ok(())
+            }
+            _ => Err(MyError::MalformedSignature(self.header())),
+        }
+    }
+
+    fn verify(self, github_secret: &str, payload: &str, signature: &str) -> bool {
+        let secret = github_secret.as_bytes();
+        let payload = payload.as_bytes();
+        // let payload = payload[..payload.len() - 1].as_bytes();
+        let prefix = match signature.strip_prefix(self.prefix()) {
+            Some(hex) => hex.as_bytes(),
+            None => return false,
+        };
+        match Vec::from_hex(prefix) {
+            Ok(sig_bytes) => {
+                let key = hmac::Key::new(self.hmac(), secret);
+                let tag = hmac::sign(&key, payload);
+                verify_slices_are_equal(tag.as_ref(), &sig_bytes).is_ok()
+            }
+            Err(_) => false,
+        }
+    }
 }
 
 pub fn authenticate(github_secret: &str, payload: &str, signature: &str) -> bool {
-    let secret = github_secret.as_bytes();
-    let payload = payload.as_bytes();
-    // let payload = payload[..payload.len() - 1].as_bytes();
-    let prefix = signature[7..signature.len()].as_bytes();
-    match Vec::from_hex(prefix) {
-        Ok(sig_bytes) => {
-            let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
-            let tag = hmac::sign(&key, payload);
-            verify_slices_are_equal(tag.as_ref(), &sig_bytes).is_ok()
-        }
-        Err(_) => false,
+    SignatureAlgorithm::Sha256.verify(github_secret, payload, signature)
+}
+
+pub fn authenticate_sha1(github_secret: &str, payload: &str, signature: &str) -> bool {
+    SignatureAlgorithm::Sha1.verify(github_secret, payload, signature)
+}
+
+fn build_client(config: &HttpConfig) -> Result<reqwest::Client, MyError> {
+    let client_error = |e: reqwest::Error| MyError::ConfigError(format!("http: {}", e));
+
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+        .timeout(Duration::from_secs(config.timeout_secs))
+        .user_agent(config.user_agent.as_str());
+    if let Some(ref proxy) = config.proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy).map_err(client_error)?);
+    }
+    for path in &config.root_certificates {
+        let pem = fs::read(path)?;
+        let cert = reqwest::Certificate::from_pem(&pem).map_err(client_error)?;
+        builder = builder.add_root_certificate(cert);
     }
+    builder.build().map_err(client_error)
+}
+
+fn header<'a>(req: &'a HttpRequest, name: &'static str) -> Result<&'a str, MyError> {
+    req.headers()
+        .get(name)
+        .ok_or(MyError::MissingHeader(name))?
+        .to_str()
+        .map_err(|_| MyError::InvalidHeader(name))
+}
+
+/// value of the `X-Github-Event` header.
+pub fn event_name(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(X_GITHUB_EVENT)
+        .and_then(|v| v.to_str().ok())
 }
 
 #[derive(Clone)]
 pub struct WebHook {
-    github_secret: Arc<String>,
-    traq_secret: Arc<String>,
-    traq_webhook_id: Arc<String>,
+    config: Arc<Config>,
+    client: reqwest::Client,
+    outbox: Arc<Outbox>,
+    store: Arc<MessageStore>,
+    templates: Arc<Templates>,
+    users: Arc<UserMap>,
+    deliveries: Arc<Mutex<Deliveries>>,
+    failed_workflows: Arc<Mutex<HashSet<String>>>,
+    celebrated: Arc<Mutex<HashSet<String>>>,
+    /// the last star/fork count seen per repository.
+    counts: Arc<Mutex<HashMap<String, u64>>>,
+    /// replaces the sinks of the targets when set.
+    sink: Option<Arc<dyn Sink + Send + Sync>>,
 }
 
 impl WebHook {
-    pub fn new(
-        github_secret: impl Into<String>,
-        traq_secret: impl Into<String>,
-        traq_webhook_id: impl Into<String>,
-    ) -> Self {
-        Self {
-            github_secret: Arc::new(github_secret.into()),
-            traq_secret: Arc::new(traq_secret.into()),
-            traq_webhook_id: Arc::new(traq_webhook_id.into()),
+    pub fn new(config: Config) -> Result<Self, MyError> {
+        if config.github.secrets.is_empty() {
+            return Err(MyError::ConfigError(
+                "at least one GitHub webhook secret is required".to_owned(),
+            ));
+        }
+
+        let outbox = Outbox::open(&config.outbox)?;
+        outbox.compact()?;
+        let store = MessageStore::open(&config.store)?;
+        let deliveries = Deliveries::new(
+            Duration::from_secs(config.dedupe.ttl_secs),
+            config.dedupe.capacity,
+        );
+        let client = build_client(&config.http)?;
+        let users = match config.users {
+            Some(ref path) => UserMap::from_file(path)?,
+            None => UserMap::default(),
+        };
+        let users = Arc::new(users);
+        let templates = Templates::new(&config, Arc::clone(&users))?;
+        Ok(Self {
+            config: Arc::new(config),
+            client,
+            outbox: Arc::new(outbox),
+            store: Arc::new(store),
+            templates: Arc::new(templates),
+            users,
+            deliveries: Arc::new(Mutex::new(deliveries)),
+            failed_workflows: Arc::new(Mutex::new(HashSet::new())),
+            celebrated: Arc::new(Mutex::new(HashSet::new())),
+            counts: Arc::new(Mutex::new(HashMap::new())),
+            sink: None,
+        })
+    }
+
+    /// sends every message to `sink` instead of the configured targets.
+    pub fn with_sink(mut self, sink: impl Sink + Send + Sync + 'static) -> Self {
+        self.sink = Some(Arc::new(sink));
+        self
+    }
+
+    pub fn outbox(&self) -> &Outbox {
+        &self.outbox
+    }
+
+    pub fn route(&self, repository: &str) -> Option<&Route> {
+        self.config.route(repository)
+    }
+
+    /// targets which events of `repository` (`owner/repo`) are sent to.
+    pub fn targets(&self, repository: &str) -> Vec<Target> {
+        self.config.targets(repository).to_vec()
+    }
+
+    /// checks the global filters and then the filters of the route of the repository,
+    /// if the event belongs to one.
+    pub fn is_allowed(&self, facts: &Facts) -> bool {
+        let route = facts
+            .repository
+            .and_then(|repository| self.config.route(repository));
+        let rules = self
+            .config
+            .filters
+            .iter()
+            .chain(route.into_iter().flat_map(|r| r.filters.iter()));
+        filter::is_allowed(rules, facts)
+    }
+
+    /// github users and the traQ users they are.
+    pub fn users(&self) -> &Arc<UserMap> {
+        &self.users
+    }
+
+    pub fn limits(&self) -> &LimitsConfig {
+        &self.config.limits
+    }
+
+    pub fn unsupported_policy(&self) -> UnsupportedPolicy {
+        self.config.unsupported
+    }
+
+    /// the highest milestone which was passed since the last count seen for `key`
+    /// and has not been celebrated yet. github does not send an event for every
+    /// star or fork, so the count may jump over a milestone.
+    pub fn reach_milestone(&self, key: &str, count: u64) -> Option<u64> {
+        let prev = self
+            .counts
+            .lock()
+            .expect("poisoned lock")
+            .insert(key.to_owned(), count)
+            // without a previous count, only an exact milestone is known to be reached
+            .unwrap_or_else(|| count.saturating_sub(1));
+        let milestone = self
+            .config
+            .milestones
+            .iter()
+            .copied()
+            .filter(|&m| prev < m && m <= count)
+            .max()?;
+        let mut celebrated = self.celebrated.lock().expect("poisoned lock");
+        if celebrated.insert(format!("{}/{}", key, milestone)) {
+            Some(milestone)
+        } else {
+            None
+        }
+    }
+
+    /// records the latest result of the workflow identified by `key`
+    /// and returns whether the previous run had failed.
+    pub fn record_workflow(&self, key: &str, failed: bool) -> bool {
+        let mut failed_workflows = self.failed_workflows.lock().expect("poisoned lock");
+        if failed {
+            !failed_workflows.insert(key.to_owned())
+        } else {
+            failed_workflows.remove(key)
         }
     }
 
@@ -61,47 +279,290 @@ impl WebHook {
         req: &mut HttpRequest,
         body: &String,
     ) -> Result<Event, MyError> {
-        let event = req.headers().get(X_GITHUB_EVENT).unwrap().to_str().unwrap();
-        let signature = req
-            .headers()
-            .get(X_HUB_SIGNATURE)
-            .unwrap()
-            .to_str()
-            .unwrap();
-
-        if !authenticate(&self.github_secret, &body, signature) {
-            return Err(MyError::UnAuthorized);
+        let content_type = req.content_type();
+        if content_type != CONTENT_TYPE_JSON {
+            return Err(MyError::UnsupportedContentType(content_type.to_owned()));
         }
 
+        let event = header(req, X_GITHUB_EVENT)?;
+        self.verify_signature(req, body)?;
+
         let payload = event::patch_payload_json(event, &body);
         let event = serde_json::from_str::<Event>(&payload)?;
+
+        // the id is forgotten again if handling the event fails
+        if let Some(delivery) = req.headers().get(X_GITHUB_DELIVERY) {
+            let delivery = delivery.to_str().unwrap_or_default();
+            let force = req
+                .headers()
+                .get(X_FORCE_REPOST)
+                .map(|v| v.as_bytes().eq_ignore_ascii_case(b"true"))
+                .unwrap_or(false);
+            if !self.check_delivery(delivery) && !force {
+                return Err(MyError::DuplicateDelivery(delivery.to_owned()));
+            }
+        }
+
         Ok(event)
     }
 
-    pub async fn post_message(&self, message: impl Into<String>) -> Result<Response, MyError> {
-        let message = message.into();
-        let url = &format!("https://q.trap.jp/api/v3/webhooks/{}", self.traq_webhook_id);
-        let client = reqwest::Client::new();
+    /// lets the delivery of `req` be processed again, after handling it failed.
+    pub fn forget_delivery(&self, req: &HttpRequest) {
+        if let Some(delivery) = req.headers().get(X_GITHUB_DELIVERY) {
+            let delivery = delivery.to_str().unwrap_or_default();
+            let mut deliveries = self.deliveries.lock().expect("poisoned lock");
+            deliveries.remove(delivery);
+        }
+    }
+
+    /// accepts the request if it is signed with any secret which is still valid.
+    /// the legacy SHA-1 header is only used when SHA-256 is missing and `allow_sha1` is set.
+    fn verify_signature(&self, req: &HttpRequest, body: &str) -> Result<(), MyError> {
+        let algorithm = if req.headers().contains_key(X_HUB_SIGNATURE) {
+            SignatureAlgorithm::Sha256
+        } else if self.config.github.allow_sha1 && req.headers().contains_key(X_HUB_SIGNATURE_SHA1)
+        {
+            SignatureAlgorithm::Sha1
+        } else {
+            return Err(MyError::MissingSignature);
+        };
+
+        let signature = header(req, algorithm.header())
+            .map_err(|_| MyError::MalformedSignature(algorithm.header()))?;
+        algorithm.validate(signature)?;
+
+        let now = Utc::now();
+        let authenticated = self
+            .config
+            .github
+            .secrets
+            .iter()
+            .filter(|s| s.is_valid_at(now))
+            .any(|s| algorithm.verify(&s.secret, body, signature));
+        if authenticated {
+            Ok(())
+        } else {
+            Err(MyError::UnAuthorized)
+        }
+    }
+
+    /// returns `false` if `delivery` has been processed recently.
+    fn check_delivery(&self, delivery: &str) -> bool {
+        if !self.config.dedupe.enabled {
+            return true;
+        }
+        let mut deliveries = self.deliveries.lock().expect("poisoned lock");
+        deliveries.insert(delivery, Instant::now())
+    }
+
+    /// renders `data` (built by `TemplateContext`) with the template configured
+    /// for `event` and its action. returns `None` if there is no such template,
+    /// or if the action is not posted (e.g. a synchronized pull request).
+    pub fn render_template(&self, event: &str, data: &Value) -> Result<Option<String>, MyError> {
+        if let Some(Value::Null) = data.get("action") {
+            return Ok(None);
+        }
+        let action = data["action"]["key"].as_str();
+        let route = data["full_name"]
+            .as_str()
+            .and_then(|repo| self.config.route_index(repo));
+        self.templates.render(route, event, action, data)
+    }
+
+    /// stores the message in the outbox. it is delivered by `outbox::run`.
+    pub fn enqueue_message(
+        &self,
+        targets: &[Target],
+        message: impl Into<String>,
+    ) -> Result<(), MyError> {
+        self.enqueue(targets, Post::new(message))
+    }
+
+    pub fn enqueue(&self, targets: &[Target], post: Post) -> Result<(), MyError> {
+        self.outbox.enqueue(targets, &post)?;
+        Ok(())
+    }
+
+    pub async fn deliver(&self, target: &Target, post: &Post) -> Result<(), MyError> {
+        match &self.sink {
+            Some(sink) => sink.send(post).await,
+            None => {
+                sink::for_target(&self.client, &self.store, &self.config.limits, target)
+                    .send(post)
+                    .await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{GithubSecret, TraqTarget};
+    use actix_web::{http::StatusCode, test::TestRequest, ResponseError};
+
+    const BODY: &str = include_str!("../tests/fixtures/issues_opened.json");
+
+    fn hook(name: &str, config: impl FnOnce(&mut Config)) -> WebHook {
+        let dir =
+            std::env::temp_dir().join(format!("portfolio_webhook-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut c = Config::single(Target::Traq(TraqTarget::new("id", "secret")));
+        c.github.secrets.push(GithubSecret::new("secret"));
+        c.outbox.path = dir.join("outbox.jsonl");
+        c.store.path = dir.join("messages.json");
+        config(&mut c);
+        WebHook::new(c).unwrap()
+    }
+
+    fn sign(algorithm: SignatureAlgorithm, secret: &str, body: &str) -> String {
+        let key = hmac::Key::new(algorithm.hmac(), secret.as_bytes());
+        let tag = hmac::sign(&key, body.as_bytes());
+        format!("{}{}", algorithm.prefix(), hex::encode(tag.as_ref()))
+    }
+
+    /// a request of an `issues` event with `headers` in addition.
+    fn request(headers: &[(&'static str, &str)]) -> HttpRequest {
+        let mut req = TestRequest::default()
+            .insert_header(("Content-Type", CONTENT_TYPE_JSON))
+            .insert_header((X_GITHUB_EVENT, "issues"));
+        for &(name, value) in headers {
+            req = req.insert_header((name, value));
+        }
+        req.to_http_request()
+    }
+
+    fn status(hook: &WebHook, mut req: HttpRequest) -> StatusCode {
+        match hook.parse_and_authenticate(&mut req, &BODY.to_owned()) {
+            Ok(_) => StatusCode::OK,
+            Err(e) => e.status_code(),
+        }
+    }
 
-        let sig = generate_signature(&message, &self.traq_secret).encode_hex::<String>();
-        let mut headers = HeaderMap::new();
-        headers.insert(X_TRAQ_SIGNATURE, sig.parse().unwrap());
-        headers.insert(
-            reqwest::header::CONTENT_TYPE,
-            "text/plain; charset=utf-8".parse().unwrap(),
+    #[test]
+    fn accepts_signed_request() {
+        let hook = hook("signed", |_| {});
+        let signature = sign(SignatureAlgorithm::Sha256, "secret", BODY);
+        assert_eq!(
+            status(&hook, request(&[(X_HUB_SIGNATURE, &signature)])),
+            StatusCode::OK
         );
+    }
+
+    #[test]
+    fn rejects_request_without_event_header() {
+        let hook = hook("no-event", |_| {});
+        let signature = sign(SignatureAlgorithm::Sha256, "secret", BODY);
+        let mut req = TestRequest::default()
+            .insert_header(("Content-Type", CONTENT_TYPE_JSON))
+            .insert_header((X_HUB_SIGNATURE, signature.as_str()))
+            .to_http_request();
+        let result = hook.parse_and_authenticate(&mut req, &BODY.to_owned());
+        assert!(matches!(
+            result,
+            Err(MyError::MissingHeader(X_GITHUB_EVENT))
+        ));
+        assert_eq!(result.unwrap_err().status_code(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn rejects_request_without_signature() {
+        let hook = hook("no-signature", |_| {});
+        assert_eq!(status(&hook, request(&[])), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn rejects_malformed_signature() {
+        let hook = hook("malformed", |_| {});
+        let signature = sign(SignatureAlgorithm::Sha256, "secret", BODY);
+        let hex = &signature["sha256=".len()..];
+        for malformed in &[hex, &signature[..signature.len() - 2], "sha256=zz"] {
+            assert!(SignatureAlgorithm::Sha256.validate(malformed).is_err());
+            assert_eq!(
+                status(&hook, request(&[(X_HUB_SIGNATURE, malformed)])),
+                StatusCode::UNAUTHORIZED
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_other_content_type() {
+        let hook = hook("content-type", |_| {});
+        let signature = sign(SignatureAlgorithm::Sha256, "secret", BODY);
+        let req = request(&[
+            (X_HUB_SIGNATURE, &signature),
+            ("Content-Type", "application/x-www-form-urlencoded"),
+        ]);
+        assert_eq!(status(&hook, req), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
 
-        let res = client
-            .post(url)
-            .headers(headers)
-            .body(message.clone())
-            .send()
-            .await?;
-        info!(
-            "Message sent to {}, message: {}, response: {:?}",
-            url, message, res
+    #[test]
+    fn accepts_any_secret_while_rotating() {
+        let hook = hook("rotating", |c| {
+            c.github.secrets.push(GithubSecret::new("old"));
+        });
+        let signature = sign(SignatureAlgorithm::Sha256, "old", BODY);
+        assert_eq!(
+            status(&hook, request(&[(X_HUB_SIGNATURE, &signature)])),
+            StatusCode::OK
         );
+    }
+
+    #[test]
+    fn rejects_expired_secret() {
+        let hook = hook("expired", |c| {
+            c.github.secrets.push(GithubSecret {
+                secret: "old".to_owned(),
+                valid_until: Some(Utc::now() - chrono::Duration::days(1)),
+            });
+        });
+        let signature = sign(SignatureAlgorithm::Sha256, "old", BODY);
+        assert_eq!(
+            status(&hook, request(&[(X_HUB_SIGNATURE, &signature)])),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[test]
+    fn accepts_sha1_only_when_allowed() {
+        let signature = sign(SignatureAlgorithm::Sha1, "secret", BODY);
+        let req = || request(&[(X_HUB_SIGNATURE_SHA1, &signature)]);
+
+        let denied = hook("sha1-denied", |_| {});
+        assert_eq!(status(&denied, req()), StatusCode::UNAUTHORIZED);
+
+        let allowed = hook("sha1-allowed", |c| c.github.allow_sha1 = true);
+        assert_eq!(status(&allowed, req()), StatusCode::OK);
+    }
+
+    #[test]
+    fn ignores_sha1_next_to_sha256() {
+        let hook = hook("sha1-ignored", |c| c.github.allow_sha1 = true);
+        let sha1 = sign(SignatureAlgorithm::Sha1, "secret", BODY);
+        let sha256 = sign(SignatureAlgorithm::Sha256, "wrong", BODY);
+        let req = request(&[(X_HUB_SIGNATURE_SHA1, &sha1), (X_HUB_SIGNATURE, &sha256)]);
+        assert_eq!(status(&hook, req), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn celebrates_milestone_which_was_passed() {
+        let hook = hook("milestones", |c| c.milestones = vec![10, 100]);
+        let key = "https://github.com/o/repo/stars";
+
+        assert_eq!(hook.reach_milestone(key, 8), None);
+        assert_eq!(hook.reach_milestone(key, 11), Some(10));
+        assert_eq!(hook.reach_milestone(key, 9), None);
+        assert_eq!(hook.reach_milestone(key, 10), None);
+        assert_eq!(hook.reach_milestone(key, 150), Some(100));
+    }
+
+    #[test]
+    fn celebrates_exact_milestone_without_previous_count() {
+        let hook = hook("first-milestone", |c| c.milestones = vec![10]);
 
-        Ok(res)
+        assert_eq!(hook.reach_milestone("a", 10), Some(10));
+        assert_eq!(hook.reach_milestone("b", 11), None);
     }
 }